@@ -0,0 +1,346 @@
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+use osmpbfreader::NodeId;
+
+use crate::graph::Graph;
+use crate::graph::NodeIndex;
+use crate::graph::INVALID_NODE;
+use crate::routing::Path;
+use crate::routing::RouteEnds;
+use crate::routing::SearchObserver;
+use crate::storage::check_indices;
+use crate::storage::corrupt;
+use crate::storage::Decoder;
+use crate::storage::Encode;
+use crate::storage::Encoder;
+use crate::storage::StorageError;
+use crate::Map;
+
+/// Strategy used to pick the landmarks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LandmarkSelection {
+    /// Uniformly random nodes.
+    Random,
+    /// Every landmark is the node farthest away from the already selected ones.
+    Farthest,
+    /// The avoid heuristic of Goldberg and Werneck, which places landmarks in the regions
+    /// where the lower bounds of the already selected landmarks are the worst.
+    Avoid,
+}
+
+impl FromStr for LandmarkSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(LandmarkSelection::Random),
+            "farthest" => Ok(LandmarkSelection::Farthest),
+            "avoid" => Ok(LandmarkSelection::Avoid),
+            _ => Err(format!("unknown landmark selection {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for LandmarkSelection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LandmarkSelection::Random => "random",
+            LandmarkSelection::Farthest => "farthest",
+            LandmarkSelection::Avoid => "avoid",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Small xorshift generator, so the selection is reproducible for a given seed.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+/// Landmarks together with their precomputed distance tables, used by ALT queries.
+#[derive(Debug, Clone)]
+pub struct Landmarks {
+    landmarks: Vec<NodeIndex>,
+    /// Distances from every landmark to all nodes, `INFINITY` if unreachable.
+    from_landmark: Vec<Vec<f64>>,
+    /// Distances from all nodes to every landmark, `INFINITY` if unreachable.
+    to_landmark: Vec<Vec<f64>>,
+}
+
+impl Landmarks {
+    /// Selects `count` landmarks on `graph` using `selection` and computes their distance
+    /// tables. `seed` drives all random choices of the selection.
+    pub fn new(graph: &Graph, count: usize, selection: LandmarkSelection, seed: u64) -> Self {
+        let candidates: Vec<NodeIndex> = (0..graph.node_count() as NodeIndex)
+            .filter(|node| graph.degree(*node) != 0)
+            .collect();
+
+        let mut landmarks = Landmarks {
+            landmarks: Vec::new(),
+            from_landmark: Vec::new(),
+            to_landmark: Vec::new(),
+        };
+        if candidates.is_empty() {
+            return landmarks;
+        }
+
+        let mut random = Random(seed.max(1));
+        let mut min_dist = vec![f64::INFINITY; graph.node_count()];
+        if selection == LandmarkSelection::Farthest {
+            let start = candidates[random.next(candidates.len())];
+            min_dist = graph.shortest_path_tree(start, false).0;
+        }
+
+        while landmarks.landmarks.len() < count.min(candidates.len()) {
+            let landmark = match selection {
+                LandmarkSelection::Random => candidates[random.next(candidates.len())],
+                LandmarkSelection::Farthest => landmarks
+                    .farthest(&min_dist)
+                    .unwrap_or_else(|| candidates[random.next(candidates.len())]),
+                LandmarkSelection::Avoid => {
+                    let root = candidates[random.next(candidates.len())];
+                    landmarks.avoid(graph, root)
+                }
+            };
+            if landmarks.landmarks.contains(&landmark) {
+                continue;
+            }
+
+            let (from_landmark, to_landmark) = rayon::join(
+                || graph.shortest_path_tree(landmark, false).0,
+                || graph.shortest_path_tree(landmark, true).0,
+            );
+            for (min, dist) in min_dist.iter_mut().zip(from_landmark.iter()) {
+                *min = min.min(*dist);
+            }
+            landmarks.landmarks.push(landmark);
+            landmarks.from_landmark.push(from_landmark);
+            landmarks.to_landmark.push(to_landmark);
+        }
+        landmarks
+    }
+
+    /// The selected landmarks.
+    pub fn landmarks(&self) -> &[NodeIndex] {
+        &self.landmarks
+    }
+
+    /// Lower bound on the distance from `from` to `to` given by the triangle inequality,
+    /// `d(L, to) - d(L, from)` and `d(from, L) - d(to, L)` for every landmark `L`.
+    pub fn lower_bound(&self, from: NodeIndex, to: NodeIndex) -> f64 {
+        let (from, to) = (from as usize, to as usize);
+        let mut bound: f64 = 0.0;
+        for (from_landmark, to_landmark) in self.from_landmark.iter().zip(self.to_landmark.iter()) {
+            if from_landmark[from].is_finite() && from_landmark[to].is_finite() {
+                bound = bound.max(from_landmark[to] - from_landmark[from]);
+            }
+            if to_landmark[from].is_finite() && to_landmark[to].is_finite() {
+                bound = bound.max(to_landmark[from] - to_landmark[to]);
+            }
+        }
+        bound
+    }
+
+    /// Whether the distance tables cover the nodes of `graph`.
+    pub(crate) fn matches(&self, graph: &Graph) -> bool {
+        self.from_landmark
+            .iter()
+            .chain(self.to_landmark.iter())
+            .all(|dist| dist.len() == graph.node_count())
+    }
+
+    /// Node with the largest finite distance to the closest already selected landmark.
+    fn farthest(&self, min_dist: &[f64]) -> Option<NodeIndex> {
+        (0..min_dist.len() as NodeIndex)
+            .filter(|node| min_dist[*node as usize].is_finite() && !self.landmarks.contains(node))
+            .max_by(|a, b| min_dist[*a as usize].total_cmp(&min_dist[*b as usize]))
+    }
+
+    /// Grows a shortest path tree from `root` and weights every node by how much its current
+    /// lower bound underestimates its distance from `root`. Subtrees containing a landmark are
+    /// ignored and the landmark is the leaf reached by always following the heaviest subtree.
+    fn avoid(&self, graph: &Graph, root: NodeIndex) -> NodeIndex {
+        let (dist, parents) = graph.shortest_path_tree(root, false);
+
+        let mut order: Vec<NodeIndex> = (0..graph.node_count() as NodeIndex)
+            .filter(|node| dist[*node as usize].is_finite())
+            .collect();
+        order.sort_by(|a, b| dist[*b as usize].total_cmp(&dist[*a as usize]));
+
+        let mut size = vec![0.0; graph.node_count()];
+        let mut has_landmark = vec![false; graph.node_count()];
+        for node in self.landmarks.iter() {
+            has_landmark[*node as usize] = true;
+        }
+        for node in order.iter().map(|node| *node as usize) {
+            size[node] += dist[node] - self.lower_bound(root, node as NodeIndex);
+            if has_landmark[node] {
+                size[node] = 0.0;
+            }
+            let parent = parents[node];
+            if parent != INVALID_NODE {
+                size[parent as usize] += size[node];
+                has_landmark[parent as usize] |= has_landmark[node];
+            }
+        }
+
+        let mut curr = root;
+        while let Some(next) = graph
+            .out_edges(curr)
+            .map(|edge| edge.node)
+            .filter(|child| parents[*child as usize] == curr && size[*child as usize] > 0.0)
+            .max_by(|a, b| size[*a as usize].total_cmp(&size[*b as usize]))
+        {
+            curr = next;
+        }
+        curr
+    }
+}
+
+impl Graph {
+    /// A* from `from` to `to` using the landmark lower bounds as the heuristic (ALT).
+    pub fn alt(&self, landmarks: &Landmarks, from: NodeIndex, to: NodeIndex) -> Option<Path> {
+        self.alt_observed(landmarks, from, to, &mut ())
+    }
+
+    /// `alt` reporting its steps to `observer`.
+    pub fn alt_observed(
+        &self,
+        landmarks: &Landmarks,
+        from: NodeIndex,
+        to: NodeIndex,
+        observer: &mut impl SearchObserver,
+    ) -> Option<Path> {
+        let heuristic = |node| landmarks.lower_bound(node, to);
+        self.guided_search(from, to, RouteEnds::default(), heuristic, observer)
+    }
+}
+
+impl Map {
+    /// ALT query, see `Graph::alt`.
+    pub fn alt(&self, landmarks: &Landmarks, from: NodeId, to: NodeId) -> Option<Path> {
+        let graph = self.graph();
+        graph.alt(landmarks, graph.index(from)?, graph.index(to)?)
+    }
+}
+
+impl Encode for Landmarks {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.landmarks.encode(encoder)?;
+        self.from_landmark.encode(encoder)?;
+        self.to_landmark.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        let landmarks = Landmarks {
+            landmarks: Encode::decode(decoder)?,
+            from_landmark: Encode::decode(decoder)?,
+            to_landmark: Encode::decode(decoder)?,
+        };
+        let count = landmarks.landmarks.len();
+        let node_count = landmarks.from_landmark.first().map_or(0, |dist| dist.len());
+        if landmarks.from_landmark.len() != count
+            || landmarks.to_landmark.len() != count
+            || landmarks
+                .from_landmark
+                .iter()
+                .chain(landmarks.to_landmark.iter())
+                .any(|dist| dist.len() != node_count)
+        {
+            return Err(corrupt("inconsistent landmark tables"));
+        }
+        check_indices(landmarks.landmarks.iter().copied(), node_count)?;
+        Ok(landmarks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LandmarkSelection;
+    use super::Landmarks;
+    use crate::graph::Graph;
+    use crate::graph::NodeIndex;
+    use crate::graph::Weighting;
+    use crate::map::fixture;
+    use crate::routing::Path;
+
+    const SELECTIONS: [LandmarkSelection; 3] = [
+        LandmarkSelection::Random,
+        LandmarkSelection::Farthest,
+        LandmarkSelection::Avoid,
+    ];
+
+    /// Weight of `path` in the weighting of `graph`.
+    fn weight(graph: &Graph, path: Option<Path>) -> Option<f64> {
+        let path = path?;
+        Some(match graph.weighting() {
+            Weighting::Distance => path.length,
+            Weighting::Time => path.duration,
+        })
+    }
+
+    #[test]
+    fn selections_pick_distinct_nodes_with_edges() {
+        let graph = fixture::grid_graph(5, Weighting::Time);
+        for selection in SELECTIONS {
+            let landmarks = Landmarks::new(&graph, 8, selection, 7);
+            let mut selected = landmarks.landmarks().to_vec();
+            assert!(selected.iter().all(|node| graph.degree(*node) != 0));
+            selected.sort_unstable();
+            selected.dedup();
+            assert_eq!(selected.len(), 8, "{}", selection);
+        }
+        // There are not more landmarks than nodes with edges.
+        let landmarks = Landmarks::new(&graph, 100, LandmarkSelection::Avoid, 7);
+        assert_eq!(landmarks.landmarks().len(), 25);
+    }
+
+    #[test]
+    fn lower_bounds_are_admissible() {
+        for weighting in [Weighting::Distance, Weighting::Time] {
+            let graph = fixture::grid_graph(6, weighting);
+            for selection in SELECTIONS {
+                let landmarks = Landmarks::new(&graph, 4, selection, 3);
+                for from in 0..graph.node_count() as NodeIndex {
+                    let (dist, _) = graph.shortest_path_tree(from, false);
+                    for to in 0..graph.node_count() as NodeIndex {
+                        let bound = landmarks.lower_bound(from, to);
+                        assert!(bound <= dist[to as usize] + 1e-9, "{} to {}", from, to);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alt_matches_dijkstra() {
+        for weighting in [Weighting::Distance, Weighting::Time] {
+            let graph = fixture::grid_graph(6, weighting);
+            for selection in SELECTIONS {
+                let landmarks = Landmarks::new(&graph, 4, selection, 11);
+                for from in 0..graph.node_count() as NodeIndex {
+                    for to in 0..graph.node_count() as NodeIndex {
+                        let expected = weight(&graph, graph.shortest_path(from, to));
+                        let found = weight(&graph, graph.alt(&landmarks, from, to));
+                        match (expected, found) {
+                            (Some(expected), Some(found)) => {
+                                assert!((expected - found).abs() < 1e-6, "{} to {}", from, to)
+                            }
+                            (expected, found) => assert_eq!(expected, found),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
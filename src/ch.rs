@@ -0,0 +1,635 @@
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use osmpbfreader::NodeId;
+use rayon::prelude::*;
+
+use crate::graph::Graph;
+use crate::graph::NodeIndex;
+use crate::routing::Path;
+use crate::routing::SearchObserver;
+use crate::routing::State;
+use crate::storage::check_indices;
+use crate::storage::check_offsets;
+use crate::storage::corrupt;
+use crate::storage::Decoder;
+use crate::storage::Encode;
+use crate::storage::Encoder;
+use crate::storage::StorageError;
+
+/// Maximum number of nodes a single witness search settles before giving up. Giving up early
+/// only adds a superfluous shortcut, so this trades preprocessing time for query time.
+const WITNESS_SEARCH_LIMIT: usize = 500;
+
+/// Shortcut replacing a path through a contracted node, as its source and its out arc.
+type Shortcut = (NodeIndex, Arc);
+
+/// Edge of the contracted graph, either an original edge or a shortcut skipping `middle`.
+/// Shortcuts carry the summed length and travel time of the edges they replace, so paths
+/// can be measured without unpacking them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Arc {
+    target: NodeIndex,
+    weight: f64,
+    length: f64,
+    duration: f64,
+    middle: Option<NodeIndex>,
+}
+
+impl Arc {
+    /// The same arc pointing to `target` instead, for storing it at its other endpoint.
+    fn reversed(self, target: NodeIndex) -> Self {
+        Arc { target, ..self }
+    }
+}
+
+/// Inserts `arc` unless there already is an arc to the same target that is not longer.
+fn add_arc(arcs: &mut Vec<Arc>, arc: Arc) {
+    match arcs.iter_mut().find(|a| a.target == arc.target) {
+        Some(existing) => {
+            if arc.weight < existing.weight {
+                *existing = arc;
+            }
+        }
+        None => arcs.push(arc),
+    }
+}
+
+/// Graph that is being contracted. Contracted nodes are removed from the adjacency lists of
+/// their neighbours, so both lists only ever contain nodes that are still in the graph.
+struct Contractor {
+    out_arcs: Vec<Vec<Arc>>,
+    in_arcs: Vec<Vec<Arc>>,
+    deleted_neighbours: Vec<i64>,
+    priority: Vec<i64>,
+}
+
+impl Contractor {
+    /// Runs a Dijkstra from `source` that avoids the nodes for which `skip` holds and stops
+    /// once it goes over `max_cost` or settles too many nodes. Returns the tentative distances.
+    fn witness_search(
+        &self,
+        source: NodeIndex,
+        skip: impl Fn(NodeIndex) -> bool,
+        max_cost: f64,
+    ) -> HashMap<NodeIndex, f64> {
+        let mut dist: HashMap<NodeIndex, f64> = HashMap::new();
+        let mut queue: BinaryHeap<State> = BinaryHeap::new();
+        let mut settled = 0;
+
+        dist.insert(source, 0.0);
+        queue.push(State {
+            cost: 0.0,
+            node: source,
+        });
+
+        while let Some(State { cost, node }) = queue.pop() {
+            if cost > dist[&node] {
+                continue;
+            }
+            settled += 1;
+            if cost > max_cost || settled > WITNESS_SEARCH_LIMIT {
+                break;
+            }
+            for arc in self.out_arcs[node as usize].iter() {
+                if skip(arc.target) {
+                    continue;
+                }
+                let new_cost = cost + arc.weight;
+                if dist.get(&arc.target).is_none_or(|&d| new_cost < d) {
+                    dist.insert(arc.target, new_cost);
+                    queue.push(State {
+                        cost: new_cost,
+                        node: arc.target,
+                    });
+                }
+            }
+        }
+        dist
+    }
+
+    /// Returns the shortcuts needed to contract `node`, with witness paths avoiding the nodes
+    /// for which `skip` holds, which has to include `node`. Nodes contracted in the same round
+    /// are skipped too, as they cannot be witnesses for each other.
+    fn shortcuts(&self, node: NodeIndex, skip: impl Fn(NodeIndex) -> bool + Copy) -> Vec<Shortcut> {
+        let out_arcs = &self.out_arcs[node as usize];
+        let max_out = out_arcs.iter().map(|a| a.weight).fold(0.0, f64::max);
+        let mut shortcuts = Vec::new();
+
+        for in_arc in self.in_arcs[node as usize].iter() {
+            let from = in_arc.target;
+            let dist = self.witness_search(from, skip, in_arc.weight + max_out);
+            for out_arc in out_arcs.iter() {
+                if out_arc.target == from {
+                    continue;
+                }
+                let via = in_arc.weight + out_arc.weight;
+                if dist.get(&out_arc.target).is_none_or(|&d| d > via) {
+                    shortcuts.push((
+                        from,
+                        Arc {
+                            target: out_arc.target,
+                            weight: via,
+                            length: in_arc.length + out_arc.length,
+                            duration: in_arc.duration + out_arc.duration,
+                            middle: Some(node),
+                        },
+                    ));
+                }
+            }
+        }
+        shortcuts
+    }
+
+    /// Edge difference of contracting `node` plus the number of its already contracted
+    /// neighbours, which keeps the contraction spread uniformly over the graph.
+    fn compute_priority(&self, node: NodeIndex) -> i64 {
+        let removed = self.out_arcs[node as usize].len() + self.in_arcs[node as usize].len();
+        let shortcuts = self.shortcuts(node, |other| other == node);
+        let edge_difference = shortcuts.len() as i64 - removed as i64;
+        edge_difference + self.deleted_neighbours[node as usize]
+    }
+
+    fn neighbours(&self, node: NodeIndex) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.out_arcs[node as usize]
+            .iter()
+            .chain(self.in_arcs[node as usize].iter())
+            .map(|a| a.target)
+    }
+
+    /// A node can be contracted in the current round if its priority is smaller than the
+    /// priority of all its neighbours, so nodes contracted together are never adjacent.
+    fn is_local_minimum(&self, node: NodeIndex) -> bool {
+        let key = (self.priority[node as usize], node);
+        self.neighbours(node)
+            .all(|n| key < (self.priority[n as usize], n))
+    }
+}
+
+/// Flattens per node adjacency lists into compressed sparse row arrays.
+fn compress(arcs: Vec<Vec<Arc>>) -> (Vec<u32>, Vec<Arc>) {
+    let mut first = Vec::with_capacity(arcs.len() + 1);
+    first.push(0);
+    for node_arcs in arcs.iter() {
+        first.push(first.last().unwrap() + node_arcs.len() as u32);
+    }
+    (first, arcs.into_iter().flatten().collect())
+}
+
+/// Contraction hierarchy over a road `Graph`.
+///
+/// Nodes are contracted in rounds of independent sets chosen by the edge difference and
+/// deleted neighbours heuristics; witness searches and priority updates of a round run in
+/// parallel. Queries are bidirectional Dijkstra searches that only go upwards in the hierarchy.
+/// The hierarchy is built on the node based graph and ignores turn restrictions, which
+/// `Router` checks its routes against.
+#[derive(Debug, Clone)]
+pub struct ContractionHierarchy {
+    ids: Vec<NodeId>,
+    rank: Vec<u32>,
+    /// Edges to higher ranked nodes, those of node `v` are `first_up[v]..first_up[v + 1]`.
+    first_up: Vec<u32>,
+    up_arcs: Vec<Arc>,
+    /// Edges from higher ranked nodes grouped by their target and pointing to their source.
+    first_down: Vec<u32>,
+    down_arcs: Vec<Arc>,
+    shortcut_count: usize,
+}
+
+impl ContractionHierarchy {
+    pub fn new(graph: &Graph) -> Self {
+        let node_count = graph.node_count();
+        let mut out_arcs: Vec<Vec<Arc>> = vec![Vec::new(); node_count];
+        let mut in_arcs: Vec<Vec<Arc>> = vec![Vec::new(); node_count];
+        for from in 0..node_count as NodeIndex {
+            for edge in graph.out_edges(from) {
+                if edge.node == from {
+                    continue;
+                }
+                let arc = Arc {
+                    target: edge.node,
+                    weight: edge.weight,
+                    length: graph.length(edge.id),
+                    duration: graph.duration(edge.id),
+                    middle: None,
+                };
+                add_arc(&mut out_arcs[from as usize], arc);
+                add_arc(&mut in_arcs[edge.node as usize], arc.reversed(from));
+            }
+        }
+
+        let mut contractor = Contractor {
+            out_arcs,
+            in_arcs,
+            deleted_neighbours: vec![0; node_count],
+            priority: vec![0; node_count],
+        };
+        contractor.priority = (0..node_count as NodeIndex)
+            .into_par_iter()
+            .map(|node| contractor.compute_priority(node))
+            .collect();
+
+        let mut rank = vec![0; node_count];
+        let mut contracted = vec![false; node_count];
+        let mut in_round = vec![false; node_count];
+        let mut up_arcs: Vec<Vec<Arc>> = vec![Vec::new(); node_count];
+        let mut down_arcs: Vec<Vec<Arc>> = vec![Vec::new(); node_count];
+        let mut shortcut_count = 0;
+        let mut next_rank = 0;
+        let mut remaining: Vec<NodeIndex> = (0..node_count as NodeIndex).collect();
+
+        while !remaining.is_empty() {
+            let round: Vec<NodeIndex> = remaining
+                .par_iter()
+                .copied()
+                .filter(|&node| contractor.is_local_minimum(node))
+                .collect();
+            for &node in round.iter() {
+                in_round[node as usize] = true;
+            }
+            let selected: Vec<(NodeIndex, Vec<Shortcut>)> = round
+                .par_iter()
+                .map(|&node| {
+                    let skip = |other: NodeIndex| in_round[other as usize];
+                    (node, contractor.shortcuts(node, skip))
+                })
+                .collect();
+
+            let mut touched: Vec<NodeIndex> = Vec::new();
+            for (node, shortcuts) in selected {
+                rank[node as usize] = next_rank;
+                contracted[node as usize] = true;
+                in_round[node as usize] = false;
+                next_rank += 1;
+
+                let node_out = std::mem::take(&mut contractor.out_arcs[node as usize]);
+                let node_in = std::mem::take(&mut contractor.in_arcs[node as usize]);
+                for arc in node_out.iter() {
+                    contractor.in_arcs[arc.target as usize].retain(|a| a.target != node);
+                    contractor.deleted_neighbours[arc.target as usize] += 1;
+                    touched.push(arc.target);
+                }
+                for arc in node_in.iter() {
+                    contractor.out_arcs[arc.target as usize].retain(|a| a.target != node);
+                    contractor.deleted_neighbours[arc.target as usize] += 1;
+                    touched.push(arc.target);
+                }
+                up_arcs[node as usize] = node_out;
+                down_arcs[node as usize] = node_in;
+
+                shortcut_count += shortcuts.len();
+                for (from, arc) in shortcuts {
+                    add_arc(&mut contractor.out_arcs[from as usize], arc);
+                    add_arc(
+                        &mut contractor.in_arcs[arc.target as usize],
+                        arc.reversed(from),
+                    );
+                }
+            }
+
+            touched.sort_unstable();
+            touched.dedup();
+            let updated: Vec<(NodeIndex, i64)> = touched
+                .par_iter()
+                .map(|&node| (node, contractor.compute_priority(node)))
+                .collect();
+            for (node, priority) in updated {
+                contractor.priority[node as usize] = priority;
+            }
+            remaining.retain(|&node| !contracted[node as usize]);
+        }
+
+        let (first_up, up_arcs) = compress(up_arcs);
+        let (first_down, down_arcs) = compress(down_arcs);
+        Self {
+            ids: (0..node_count as NodeIndex)
+                .map(|node| graph.id(node))
+                .collect(),
+            rank,
+            first_up,
+            up_arcs,
+            first_down,
+            down_arcs,
+            shortcut_count,
+        }
+    }
+
+    /// Number of shortcuts added during the preprocessing.
+    pub fn shortcut_count(&self) -> usize {
+        self.shortcut_count
+    }
+
+    /// Returns the shortest path between `from` and `to` using an upward search from both
+    /// ends, with shortcuts unpacked back to the original nodes. Steps along shortcuts are
+    /// reported to `observer` between the endpoints of the shortcut.
+    ///
+    /// The hierarchy knows nothing of turn restrictions, so the path may make forbidden
+    /// turns. `Router` replaces such paths by restricted ones.
+    pub(crate) fn shortest_path(
+        &self,
+        source: NodeIndex,
+        target: NodeIndex,
+        observer: &mut impl SearchObserver,
+    ) -> Option<Path> {
+        let mut dist: [HashMap<NodeIndex, f64>; 2] = [HashMap::new(), HashMap::new()];
+        let mut parents: [HashMap<NodeIndex, NodeIndex>; 2] = [HashMap::new(), HashMap::new()];
+        let mut queues: [BinaryHeap<State>; 2] = [BinaryHeap::new(), BinaryHeap::new()];
+        let mut settled = 0;
+        let mut best = f64::INFINITY;
+        let mut meeting = source;
+
+        for (side, start) in [source, target].into_iter().enumerate() {
+            dist[side].insert(start, 0.0);
+            queues[side].push(State {
+                cost: 0.0,
+                node: start,
+            });
+        }
+
+        loop {
+            let min_keys = queues
+                .each_ref()
+                .map(|q| q.peek().map_or(f64::INFINITY, |s| s.cost));
+            if min_keys[0] >= best && min_keys[1] >= best {
+                break;
+            }
+            let side = if min_keys[0] <= min_keys[1] { 0 } else { 1 };
+            let State { cost, node } = queues[side].pop().unwrap();
+            if cost > dist[side][&node] {
+                continue;
+            }
+            settled += 1;
+            observer.settled(node, side == 0);
+            if let Some(other_cost) = dist[1 - side].get(&node) {
+                if cost + other_cost < best {
+                    best = cost + other_cost;
+                    meeting = node;
+                }
+            }
+
+            let arcs = if side == 0 {
+                self.up(node)
+            } else {
+                self.down(node)
+            };
+            for arc in arcs.iter() {
+                let new_cost = cost + arc.weight;
+                if dist[side].get(&arc.target).is_none_or(|&d| new_cost < d) {
+                    dist[side].insert(arc.target, new_cost);
+                    parents[side].insert(arc.target, node);
+                    observer.relaxed(node, arc.target, side == 0);
+                    queues[side].push(State {
+                        cost: new_cost,
+                        node: arc.target,
+                    });
+                }
+            }
+        }
+
+        if best == f64::INFINITY {
+            return None;
+        }
+
+        let mut hierarchy_path = vec![meeting];
+        let mut curr = meeting;
+        while curr != source {
+            curr = parents[0][&curr];
+            hierarchy_path.push(curr);
+        }
+        hierarchy_path.reverse();
+        curr = meeting;
+        while curr != target {
+            curr = parents[1][&curr];
+            hierarchy_path.push(curr);
+        }
+
+        let mut nodes = vec![self.ids[source as usize]];
+        let (mut length, mut duration) = (0.0, 0.0);
+        for pair in hierarchy_path.windows(2) {
+            let arc = self.find_arc(pair[0], pair[1]);
+            length += arc.length;
+            duration += arc.duration;
+            self.unpack_arc(pair[0], pair[1], &mut nodes);
+        }
+        Some(Path {
+            length,
+            duration,
+            nodes,
+            settled,
+        })
+    }
+
+    fn up(&self, node: NodeIndex) -> &[Arc] {
+        &self.up_arcs
+            [self.first_up[node as usize] as usize..self.first_up[node as usize + 1] as usize]
+    }
+
+    fn down(&self, node: NodeIndex) -> &[Arc] {
+        &self.down_arcs
+            [self.first_down[node as usize] as usize..self.first_down[node as usize + 1] as usize]
+    }
+
+    /// Arc going from `from` to `to`, stored at whichever of the two nodes has the lower rank.
+    fn arc(&self, from: NodeIndex, to: NodeIndex) -> Option<&Arc> {
+        if self.rank[from as usize] < self.rank[to as usize] {
+            self.up(from).iter().find(|a| a.target == to)
+        } else {
+            self.down(to).iter().find(|a| a.target == from)
+        }
+    }
+
+    /// Finds the arc going from `from` to `to`, which `decode` checked for every shortcut.
+    fn find_arc(&self, from: NodeIndex, to: NodeIndex) -> &Arc {
+        self.arc(from, to).unwrap()
+    }
+
+    /// Whether the hierarchy was built for the nodes of `graph`.
+    pub(crate) fn matches(&self, graph: &Graph) -> bool {
+        self.ids.len() == graph.node_count()
+            && self
+                .ids
+                .iter()
+                .enumerate()
+                .all(|(node, &id)| graph.id(node as NodeIndex) == id)
+    }
+
+    /// Checks that the ranks are a permutation, that arcs lead upwards, and that both halves
+    /// of every shortcut exist below its ends so unpacking terminates.
+    fn check_structure(&self) -> Result<(), StorageError> {
+        let mut seen = vec![false; self.rank.len()];
+        for &rank in &self.rank {
+            if seen.get(rank as usize).copied() != Some(false) {
+                return Err(corrupt("hierarchy ranks are not a permutation"));
+            }
+            seen[rank as usize] = true;
+        }
+        for node in 0..self.ids.len() as NodeIndex {
+            let up = self.up(node).iter().map(|arc| (node, arc.target, arc));
+            let down = self.down(node).iter().map(|arc| (arc.target, node, arc));
+            for (from, to, arc) in up.chain(down) {
+                // Both kinds of arcs are stored at their lower ranked end.
+                if self.rank[node as usize] >= self.rank[arc.target as usize] {
+                    return Err(corrupt("hierarchy arc does not lead upwards"));
+                }
+                if let Some(middle) = arc.middle {
+                    let rank = self.rank[middle as usize];
+                    if rank >= self.rank[from as usize]
+                        || rank >= self.rank[to as usize]
+                        || self.arc(from, middle).is_none()
+                        || self.arc(middle, to).is_none()
+                    {
+                        return Err(corrupt("invalid hierarchy shortcut"));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the original nodes of the arc from `from` to `to`, except for `from` itself.
+    fn unpack_arc(&self, from: NodeIndex, to: NodeIndex, nodes: &mut Vec<NodeId>) {
+        let mut stack = vec![(from, to)];
+        while let Some((from, to)) = stack.pop() {
+            match self.find_arc(from, to).middle {
+                Some(middle) => {
+                    stack.push((middle, to));
+                    stack.push((from, middle));
+                }
+                None => nodes.push(self.ids[to as usize]),
+            }
+        }
+    }
+}
+
+impl Encode for Arc {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.target.encode(encoder)?;
+        self.weight.encode(encoder)?;
+        self.length.encode(encoder)?;
+        self.duration.encode(encoder)?;
+        self.middle.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        Ok(Arc {
+            target: Encode::decode(decoder)?,
+            weight: Encode::decode(decoder)?,
+            length: Encode::decode(decoder)?,
+            duration: Encode::decode(decoder)?,
+            middle: Encode::decode(decoder)?,
+        })
+    }
+}
+
+impl Encode for ContractionHierarchy {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.ids.encode(encoder)?;
+        self.rank.encode(encoder)?;
+        self.first_up.encode(encoder)?;
+        self.up_arcs.encode(encoder)?;
+        self.first_down.encode(encoder)?;
+        self.down_arcs.encode(encoder)?;
+        self.shortcut_count.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        let hierarchy = ContractionHierarchy {
+            ids: Encode::decode(decoder)?,
+            rank: Encode::decode(decoder)?,
+            first_up: Encode::decode(decoder)?,
+            up_arcs: Encode::decode(decoder)?,
+            first_down: Encode::decode(decoder)?,
+            down_arcs: Encode::decode(decoder)?,
+            shortcut_count: Encode::decode(decoder)?,
+        };
+        let node_count = hierarchy.ids.len();
+        if hierarchy.rank.len() != node_count {
+            return Err(corrupt("inconsistent hierarchy arrays"));
+        }
+        check_offsets(&hierarchy.first_up, node_count, hierarchy.up_arcs.len())?;
+        check_offsets(&hierarchy.first_down, node_count, hierarchy.down_arcs.len())?;
+        let arcs = hierarchy.up_arcs.iter().chain(hierarchy.down_arcs.iter());
+        check_indices(
+            arcs.flat_map(|arc| std::iter::once(arc.target).chain(arc.middle)),
+            node_count,
+        )?;
+        hierarchy.check_structure()?;
+        Ok(hierarchy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use osmpbfreader::NodeId;
+
+    use super::ContractionHierarchy;
+    use crate::graph::Graph;
+    use crate::graph::NodeIndex;
+    use crate::map::fixture;
+
+    /// Graph of two way roads between nodes numbered from 1 at `coordinates`.
+    fn graph(coordinates: &[(f64, f64)], roads: &[[i64; 2]]) -> Graph {
+        let roads: Vec<(i64, &[i64])> = roads
+            .iter()
+            .enumerate()
+            .map(|(id, road)| (id as i64, &road[..]))
+            .collect();
+        fixture::two_way_graph(&fixture::nodes(coordinates), &fixture::ways(&roads))
+    }
+
+    fn assert_same_as_dijkstra(graph: &Graph) {
+        let hierarchy = ContractionHierarchy::new(graph);
+        for from in 0..graph.node_count() as NodeIndex {
+            for to in 0..graph.node_count() as NodeIndex {
+                let expected = graph.shortest_path(from, to).map(|path| path.length);
+                let found = hierarchy
+                    .shortest_path(from, to, &mut ())
+                    .map(|path| path.length);
+                match (expected, found) {
+                    (Some(expected), Some(found)) => {
+                        assert!((expected - found).abs() < 1e-6, "{} to {}", from, to)
+                    }
+                    (expected, found) => assert_eq!(expected, found, "{} to {}", from, to),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn equal_weight_paths_through_one_round() {
+        // Mirrored around the equator, both paths from 3 to 4 are exactly as long, and the
+        // middle nodes 1 and 2 have the lowest ids, so they are contracted in the same round.
+        let coordinates = [(0.001, 0.001), (-0.001, 0.001), (0.0, 0.0), (0.0, 0.002)];
+        let graph = graph(&coordinates, &[[3, 1], [1, 4], [3, 2], [2, 4]]);
+        let hierarchy = ContractionHierarchy::new(&graph);
+        let (from, to) = (
+            graph.index(NodeId(3)).unwrap(),
+            graph.index(NodeId(4)).unwrap(),
+        );
+        assert!(hierarchy.shortest_path(from, to, &mut ()).is_some());
+        assert_same_as_dijkstra(&graph);
+    }
+
+    #[test]
+    fn grid_with_many_shortest_paths() {
+        let size = 6;
+        let id = |row: i64, column: i64| row * size + column + 1;
+        let mut coordinates = Vec::new();
+        let mut roads = Vec::new();
+        for row in 0..size {
+            for column in 0..size {
+                let lat = (row - size / 2) as f64 * 0.001;
+                coordinates.push((lat, column as f64 * 0.001));
+                if column + 1 < size {
+                    roads.push([id(row, column), id(row, column + 1)]);
+                }
+                if row + 1 < size {
+                    roads.push([id(row, column), id(row + 1, column)]);
+                }
+            }
+        }
+        assert_same_as_dijkstra(&graph(&coordinates, &roads));
+    }
+}
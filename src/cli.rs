@@ -0,0 +1,311 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use osmpbfreader::NodeId;
+
+use shortest_path::alt::LandmarkSelection;
+use shortest_path::graph::Weighting;
+use shortest_path::profile::Profile;
+use shortest_path::projection::Projection;
+use shortest_path::router::Algorithm;
+use shortest_path::DanglingPolicy;
+use shortest_path::Overlay;
+use shortest_path::Picture;
+use shortest_path::Prune;
+
+pub const USAGE: &str = "\
+Usage: shortest_path <command> <input.osm.pbf> [arguments] [options]
+
+Commands:
+  stats                    print the size of the routing graph
+  route <from> <to>        find routes between two OSM node ids or lat,lon coordinates,
+                           which are snapped to the nearest road, with the speedup over
+                           dijkstra in settled nodes if it is selected as well
+  components               print the connected components and trap nodes
+  preprocess               build the contraction hierarchy and the landmarks and write
+                           them with the graph to a graph file
+  render                   draw the road network in a window, left clicks pick the
+                           origin and the destination of a route, whose search is
+                           animated, space pauses, [ and ] change the speed, tab
+                           switches algorithms, dragging and the wheel pan and zoom
+  image <output.png> [<from> <to>]
+                           save a picture of the road network without a window, with
+                           the routes of all selected algorithms if endpoints are given
+  serve                    answer GET /route?from=<location>&to=<location> requests over
+                           HTTP, with node ids or lat,lon coordinates as locations
+
+Options:
+  --profile <car|bicycle|foot>          mode of transport, car by default
+  --weighting <time|distance>           metric to minimise, time by default
+  --algorithm <name[,name...]|all>      dijkstra, astar, bidijkstra, biastar, ch or alt,
+                                        dijkstra by default
+  --format <text|json|geojson>          output format, text by default
+  --landmarks <count>                   number of ALT landmarks, 16 by default
+  --selection <random|farthest|avoid>   ALT landmark selection, avoid by default
+  --address <host:port>                 address to serve on, 127.0.0.1:8080 by default
+  --graph <file>                        read the graph from a graph file written by
+                                        preprocess instead of the input, if it is up to date
+  --output <file>                       graph file written by preprocess, the input with
+                                        a .graph extension by default
+  --dangling <fail|skip|truncate>       handling of ways with nodes missing from the input,
+                                        truncate by default
+  --prune <none|largest|strong|min_lat,min_lon,max_lat,max_lon>
+                                        keep only the largest weakly or strongly connected
+                                        component, or the weakly connected components
+                                        reaching into the box, none by default
+  --projection <mercator|equirectangular>
+                                        map projection of render and image, mercator by
+                                        default
+  --size <width>x<height>               size of the image in pixels, 1600x800 by default
+  --bbox <min_lat,min_lon,max_lat,max_lon>
+                                        region shown in the image, the whole map by default
+  --overlay <none|tree|components>      drawn over the network in the image: the search tree
+                                        of Dijkstra up to the destination or the connected
+                                        components, none by default";
+
+/// Output format of the commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    /// GeoJSON feature collection, routes become line strings.
+    GeoJson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "geojson" => Ok(OutputFormat::GeoJson),
+            _ => Err(format!("unknown output format {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::GeoJson => "geojson",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Start or end of a route.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Location {
+    Node(NodeId),
+    /// Latitude and longitude in degrees, snapped to the nearest road.
+    Coordinates(f64, f64),
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid location {}", s);
+        match s.split_once(',') {
+            Some((lat, lon)) => {
+                let lat: f64 = lat.trim().parse().map_err(|_| invalid())?;
+                let lon: f64 = lon.trim().parse().map_err(|_| invalid())?;
+                if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                    return Err(invalid());
+                }
+                Ok(Location::Coordinates(lat, lon))
+            }
+            None => s
+                .parse()
+                .map(|id| Location::Node(NodeId(id)))
+                .map_err(|_| invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Node(id) => write!(f, "{}", id.0),
+            Location::Coordinates(lat, lon) => write!(f, "{},{}", lat, lon),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Stats,
+    Route {
+        from: Location,
+        to: Location,
+    },
+    Components,
+    Preprocess {
+        output: PathBuf,
+    },
+    Render,
+    Image {
+        output: PathBuf,
+        /// Origin and destination of the routes to draw.
+        endpoints: Option<(Location, Location)>,
+    },
+    Serve {
+        address: String,
+    },
+}
+
+/// Parsed command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub input: PathBuf,
+    /// Graph file to read instead of the input.
+    pub graph: Option<PathBuf>,
+    pub profile: Profile,
+    pub weighting: Weighting,
+    pub algorithms: Vec<Algorithm>,
+    pub format: OutputFormat,
+    pub landmark_count: usize,
+    pub selection: LandmarkSelection,
+    pub dangling: DanglingPolicy,
+    pub prune: Prune,
+    /// Map projection of the drawings.
+    pub projection: Projection,
+    pub picture: Picture,
+}
+
+fn parse_value<T: FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value of {}", option))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} of {}", value, option))
+}
+
+/// Parses `<width>x<height>`.
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid size {}", value);
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
+fn parse_algorithms(value: &str) -> Result<Vec<Algorithm>, String> {
+    if value == "all" {
+        return Ok(Algorithm::ALL.to_vec());
+    }
+    value.split(',').map(|name| name.parse()).collect()
+}
+
+impl Options {
+    /// Parses the arguments following the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let mut positional: Vec<String> = Vec::new();
+        let mut profile = Profile::Car;
+        let mut weighting = Weighting::Time;
+        let mut algorithms = vec![Algorithm::Dijkstra];
+        let mut format = OutputFormat::Text;
+        let mut landmark_count = 16;
+        let mut selection = LandmarkSelection::Avoid;
+        let mut address = String::from("127.0.0.1:8080");
+        let mut dangling = DanglingPolicy::Truncate;
+        let mut graph = None;
+        let mut prune = Prune::None;
+        let mut projection = Projection::WebMercator;
+        let mut picture = Picture::default();
+        let mut output = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--profile" => profile = parse_value(&arg, args.next())?,
+                "--weighting" => weighting = parse_value(&arg, args.next())?,
+                "--algorithm" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    algorithms = parse_algorithms(&value)?;
+                }
+                "--format" => format = parse_value(&arg, args.next())?,
+                "--landmarks" => landmark_count = parse_value(&arg, args.next())?,
+                "--selection" => selection = parse_value(&arg, args.next())?,
+                "--address" => address = parse_value(&arg, args.next())?,
+                "--dangling" => dangling = parse_value(&arg, args.next())?,
+                "--graph" => graph = Some(parse_value::<PathBuf>(&arg, args.next())?),
+                "--prune" => prune = parse_value(&arg, args.next())?,
+                "--projection" => projection = parse_value(&arg, args.next())?,
+                "--size" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    (picture.width, picture.height) = parse_size(&value)?;
+                }
+                "--bbox" => picture.region = Some(parse_value(&arg, args.next())?),
+                "--overlay" => picture.overlay = parse_value(&arg, args.next())?,
+                "--output" => output = Some(parse_value::<PathBuf>(&arg, args.next())?),
+                "-h" | "--help" => return Err(String::new()),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let command = positional.next().ok_or("missing command")?;
+        let input = PathBuf::from(positional.next().ok_or("missing input file")?);
+        let command = match command.as_str() {
+            "stats" => Command::Stats,
+            "route" => {
+                let from = parse_value("<from>", positional.next())?;
+                let to = parse_value("<to>", positional.next())?;
+                Command::Route { from, to }
+            }
+            "components" => Command::Components,
+            "preprocess" => Command::Preprocess {
+                output: output.unwrap_or_else(|| {
+                    let mut output = input.clone().into_os_string();
+                    output.push(".graph");
+                    PathBuf::from(output)
+                }),
+            },
+            "render" => Command::Render,
+            "image" => {
+                let output = parse_value("<output.png>", positional.next())?;
+                let endpoints = match positional.next() {
+                    Some(from) => Some((
+                        parse_value("<from>", Some(from))?,
+                        parse_value("<to>", positional.next())?,
+                    )),
+                    None => None,
+                };
+                if endpoints.is_none() && picture.overlay == Overlay::Tree {
+                    return Err(String::from("the tree overlay needs <from> and <to>"));
+                }
+                Command::Image { output, endpoints }
+            }
+            "serve" => Command::Serve { address },
+            _ => return Err(format!("unknown command {}", command)),
+        };
+        if let Some(arg) = positional.next() {
+            return Err(format!("unexpected argument {}", arg));
+        }
+
+        Ok(Options {
+            command,
+            input,
+            graph,
+            profile,
+            weighting,
+            algorithms,
+            format,
+            landmark_count,
+            selection,
+            dangling,
+            prune,
+            projection,
+            picture,
+        })
+    }
+}
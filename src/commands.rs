@@ -0,0 +1,552 @@
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::time::Instant;
+
+use crate::cli::Location;
+use crate::cli::Options;
+use crate::cli::OutputFormat;
+use shortest_path::alt::Landmarks;
+use shortest_path::ch::ContractionHierarchy;
+use shortest_path::graph::NodeIndex;
+use shortest_path::router::Algorithm;
+use shortest_path::router::Preprocessing;
+use shortest_path::router::Router;
+use shortest_path::routing::Path;
+use shortest_path::snap::Snap;
+use shortest_path::storage;
+use shortest_path::storage::Header;
+use shortest_path::Map;
+use shortest_path::MapDrawing;
+
+/// Components smaller than this are only counted in the text output.
+const LISTED_COMPONENT_SIZE: usize = 500;
+
+/// At most this many trap nodes are listed in the text output.
+const LISTED_TRAP_COUNT: usize = 20;
+
+/// Prints the size of the routing graph.
+pub fn stats(options: &Options, map: &Map) {
+    let graph = map.graph();
+    let component_count = graph.component_sizes().len();
+    let restriction_count = map.restriction_count();
+    let forbidden_turns = graph.turn_restrictions().sequence_count();
+    match options.format {
+        OutputFormat::Text => {
+            println!(
+                "Graph for the {} profile weighted by {} has {} nodes and {} edges from {} ways",
+                map.profile(),
+                graph.weighting(),
+                graph.node_count(),
+                graph.edge_count(),
+                map.way_count()
+            );
+            println!(
+                "{} turn restrictions give {} forbidden turns",
+                restriction_count, forbidden_turns
+            );
+            println!("Number of components is {}", component_count);
+        }
+        OutputFormat::Json | OutputFormat::GeoJson => println!(
+            "{{\"profile\":\"{}\",\"weighting\":\"{}\",\"nodes\":{},\"edges\":{},\"ways\":{},\
+             \"restrictions\":{},\"forbidden_turns\":{},\"components\":{}}}",
+            map.profile(),
+            graph.weighting(),
+            graph.node_count(),
+            graph.edge_count(),
+            map.way_count(),
+            restriction_count,
+            forbidden_turns,
+            component_count
+        ),
+    }
+}
+
+/// Prints the weakly and strongly connected components of the graph and the trap nodes
+/// outside the largest strongly connected component.
+pub fn components(options: &Options, map: &Map) {
+    let graph = map.graph();
+    let sizes = graph.component_sizes();
+    let strong = graph.strong_components();
+    let traps = graph.traps(&strong);
+    let ids = |nodes: &[NodeIndex]| -> Vec<String> {
+        nodes
+            .iter()
+            .map(|node| graph.id(*node).0.to_string())
+            .collect()
+    };
+    match options.format {
+        OutputFormat::Text => {
+            println!("Number of components is {}", sizes.len());
+            for size in sizes.iter().take_while(|s| **s > LISTED_COMPONENT_SIZE) {
+                println!("Component size is {}", size);
+            }
+            println!(
+                "Number of strongly connected components is {}",
+                strong.count()
+            );
+            if let Some(size) = strong.sizes().first() {
+                println!("Largest strongly connected component has {} nodes", size);
+            }
+            for (nodes, description) in [
+                (&traps.no_exit, "can be reached from it but cannot get back"),
+                (
+                    &traps.no_entry,
+                    "can reach it but cannot be reached from it",
+                ),
+            ] {
+                let mut listed = ids(&nodes[..nodes.len().min(LISTED_TRAP_COUNT)]);
+                if nodes.len() > LISTED_TRAP_COUNT {
+                    listed.push(String::from("..."));
+                }
+                println!(
+                    "{} nodes {}: {}",
+                    nodes.len(),
+                    description,
+                    listed.join(" ")
+                );
+            }
+        }
+        OutputFormat::Json | OutputFormat::GeoJson => {
+            let sizes: Vec<String> = sizes.iter().map(|size| size.to_string()).collect();
+            let strong_sizes: Vec<String> =
+                strong.sizes().iter().map(|size| size.to_string()).collect();
+            println!(
+                "{{\"components\":[{}],\"strong_components\":[{}],\"no_exit\":[{}],\
+                 \"no_entry\":[{}]}}",
+                sizes.join(","),
+                strong_sizes.join(","),
+                ids(&traps.no_exit).join(","),
+                ids(&traps.no_entry).join(",")
+            );
+        }
+    }
+}
+
+/// Builds the contraction hierarchy and the landmarks, reports how long it took and writes
+/// them together with `map` to the graph file `output`.
+pub fn preprocess(
+    options: &Options,
+    map: &Map,
+    header: &Header,
+    output: &std::path::Path,
+) -> io::Result<()> {
+    let start = Instant::now();
+    let hierarchy = ContractionHierarchy::new(map.graph());
+    let hierarchy_time = start.elapsed();
+    let start = Instant::now();
+    let landmarks = Landmarks::new(map.graph(), options.landmark_count, options.selection, 42);
+    let landmark_time = start.elapsed();
+    let start = Instant::now();
+    storage::save(output, header, map, Some(&hierarchy), Some(&landmarks))?;
+    let write_time = start.elapsed();
+    match options.format {
+        OutputFormat::Text => {
+            println!(
+                "Contraction hierarchy with {} shortcuts built in {:.1?}",
+                hierarchy.shortcut_count(),
+                hierarchy_time
+            );
+            println!(
+                "{} {} landmarks selected in {:.1?}",
+                landmarks.landmarks().len(),
+                options.selection,
+                landmark_time
+            );
+            println!(
+                "Graph file {} written in {:.1?}",
+                output.display(),
+                write_time
+            );
+        }
+        OutputFormat::Json | OutputFormat::GeoJson => println!(
+            "{{\"shortcuts\":{},\"hierarchy_seconds\":{:.3},\"landmarks\":{},\
+             \"selection\":\"{}\",\"landmark_seconds\":{:.3},\"output\":{:?},\
+             \"write_seconds\":{:.3}}}",
+            hierarchy.shortcut_count(),
+            hierarchy_time.as_secs_f64(),
+            landmarks.landmarks().len(),
+            options.selection,
+            landmark_time.as_secs_f64(),
+            output.display().to_string(),
+            write_time.as_secs_f64()
+        ),
+    }
+    Ok(())
+}
+
+/// Shows the map in a window where routes can be picked with the mouse, animating the
+/// searches of the selected algorithms.
+pub fn render(options: &Options, map: &Map, preprocessing: Preprocessing) {
+    let router = Router::new(
+        map,
+        preprocessing,
+        &options.algorithms,
+        options.landmark_count,
+        options.selection,
+    );
+    MapDrawing::new(options.projection).draw(&router, &options.algorithms);
+}
+
+/// Saves a picture of the map to `output`, with the routes from `from` to `to` found by
+/// all selected algorithms if `endpoints` are given.
+pub fn image(
+    options: &Options,
+    map: &Map,
+    preprocessing: Preprocessing,
+    output: &std::path::Path,
+    endpoints: Option<(Location, Location)>,
+) -> Result<(), String> {
+    let mut paths = Vec::new();
+    if let Some((from, to)) = endpoints {
+        let router = Router::new(
+            map,
+            preprocessing,
+            &options.algorithms,
+            options.landmark_count,
+            options.selection,
+        );
+        let from = Endpoint::new(&router, from)?;
+        let to = Endpoint::new(&router, to)?;
+        for (algorithm, path) in find_routes(&router, &options.algorithms, &from, &to) {
+            match path {
+                Some(path) => paths.push(path),
+                None => eprintln!("{}: no route", algorithm),
+            }
+        }
+    }
+    let start = Instant::now();
+    MapDrawing::new(options.projection).save(map, &options.picture, &paths, output)?;
+    eprintln!("Drawing {} took {:.1?}", output.display(), start.elapsed());
+    Ok(())
+}
+
+/// Location given by the user together with the point of the road it stands for.
+struct Endpoint {
+    location: Location,
+    snap: Snap,
+}
+
+impl Endpoint {
+    fn new(router: &Router, location: Location) -> Result<Self, String> {
+        let graph = router.map().graph();
+        let snap = match location {
+            Location::Node(id) => graph
+                .index(id)
+                .and_then(|node| Snap::at_node(graph, node))
+                .ok_or_else(|| format!("node {} is not part of the graph", id.0))?,
+            Location::Coordinates(lat, lon) => router
+                .snap(lat, lon)
+                .ok_or_else(|| String::from("there are no roads to snap to"))?,
+        };
+        Ok(Endpoint { location, snap })
+    }
+
+    /// Node id, or the coordinates with the point they were snapped to.
+    fn json(&self) -> String {
+        match self.location {
+            Location::Node(id) => id.0.to_string(),
+            Location::Coordinates(lat, lon) => format!(
+                "{{\"lat\":{},\"lon\":{},\"snapped\":[{:.7},{:.7}],\"distance\":{:.1}}}",
+                lat, lon, self.snap.lon, self.snap.lat, self.snap.distance
+            ),
+        }
+    }
+}
+
+/// Routes from `from` to `to` found by every algorithm in `algorithms`. Routes between two
+/// nodes are searched directly, others start and end on the snapped points.
+fn find_routes(
+    router: &Router,
+    algorithms: &[Algorithm],
+    from: &Endpoint,
+    to: &Endpoint,
+) -> Vec<(Algorithm, Option<Path>)> {
+    algorithms
+        .iter()
+        .map(|algorithm| {
+            let path = match (from.location, to.location) {
+                (Location::Node(from), Location::Node(to)) => router.route(*algorithm, from, to),
+                _ => router.route_between(*algorithm, &from.snap, &to.snap),
+            };
+            (*algorithm, path)
+        })
+        .collect()
+}
+
+/// Prints the routes from `from` to `to` found by all selected algorithms.
+pub fn route(
+    options: &Options,
+    map: &Map,
+    preprocessing: Preprocessing,
+    from: Location,
+    to: Location,
+) {
+    let router = Router::new(
+        map,
+        preprocessing,
+        &options.algorithms,
+        options.landmark_count,
+        options.selection,
+    );
+    let endpoints =
+        Endpoint::new(&router, from).and_then(|from| Ok((from, Endpoint::new(&router, to)?)));
+    let (from, to) = match endpoints {
+        Ok(endpoints) => endpoints,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    if options.format == OutputFormat::Text {
+        for endpoint in [&from, &to] {
+            if let Location::Coordinates(..) = endpoint.location {
+                println!(
+                    "{} snapped to {:.7},{:.7}, {:.1} m away",
+                    endpoint.location, endpoint.snap.lat, endpoint.snap.lon, endpoint.snap.distance
+                );
+            }
+        }
+    }
+    let routes = find_routes(&router, &options.algorithms, &from, &to);
+    print!(
+        "{}",
+        format_routes(options.format, map, &from, &to, &routes)
+    );
+}
+
+/// Answers `GET /route?from=<location>&to=<location>[&algorithm=<name>]` requests on the
+/// address of the serve command, one at a time. Locations are node ids or `lat,lon`.
+pub fn serve(
+    options: &Options,
+    map: &Map,
+    preprocessing: Preprocessing,
+    address: &str,
+) -> io::Result<()> {
+    let router = Router::new(
+        map,
+        preprocessing,
+        &options.algorithms,
+        options.landmark_count,
+        options.selection,
+    );
+    let listener = TcpListener::bind(address)?;
+    println!("Listening on http://{}", address);
+    for stream in listener.incoming() {
+        if let Err(err) = respond(options, &router, stream?) {
+            eprintln!("Request failed: {}", err);
+        }
+    }
+    Ok(())
+}
+
+fn respond(options: &Options, router: &Router, mut stream: TcpStream) -> io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let (status, body) = match handle_request(options, router, &request_line) {
+        Ok(body) => ("200 OK", body),
+        Err(err) => ("400 Bad Request", format!("{}\n", err)),
+    };
+    let content_type = match options.format {
+        OutputFormat::Text => "text/plain",
+        OutputFormat::Json => "application/json",
+        OutputFormat::GeoJson => "application/geo+json",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+fn handle_request(
+    options: &Options,
+    router: &Router,
+    request_line: &str,
+) -> Result<String, String> {
+    let mut parts = request_line.split_whitespace();
+    let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(String::from("only GET requests are supported"));
+    };
+    let query = match target.split_once('?') {
+        Some(("/route", query)) => query,
+        _ => return Err(format!("unknown path {}", target)),
+    };
+
+    let mut from = None;
+    let mut to = None;
+    let mut algorithm = options.algorithms[0];
+    for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        match key {
+            "from" => from = Some(value.replace("%2C", ",").parse::<Location>()?),
+            "to" => to = Some(value.replace("%2C", ",").parse::<Location>()?),
+            "algorithm" => algorithm = value.parse()?,
+            _ => {}
+        }
+    }
+    let (Some(from), Some(to)) = (from, to) else {
+        return Err(String::from("from and to are required"));
+    };
+    if !options.algorithms.contains(&algorithm) {
+        return Err(format!("algorithm {} is not enabled", algorithm));
+    }
+    let (from, to) = (Endpoint::new(router, from)?, Endpoint::new(router, to)?);
+    let routes = find_routes(router, &[algorithm], &from, &to);
+    Ok(format_routes(
+        options.format,
+        router.map(),
+        &from,
+        &to,
+        &routes,
+    ))
+}
+
+/// Coordinates of the nodes of `path` as `[lon,lat]` pairs, starting and ending with the
+/// snapped points of endpoints given by coordinates. A snapped point on the first or the last
+/// edge of the path replaces the node of that edge beyond it, as the route does not drive
+/// there, e.g. for routes staying on a single edge.
+fn path_coordinates(map: &Map, path: &Path, from: &Endpoint, to: &Endpoint) -> String {
+    let graph = map.graph();
+    let nodes: Vec<NodeIndex> = path
+        .nodes
+        .iter()
+        .filter_map(|id| graph.index(*id))
+        .collect();
+    // Whether `endpoint` is snapped to the segment between `a` and `b`, in either direction.
+    let on_segment = |endpoint: &Endpoint, a: NodeIndex, b: NodeIndex| {
+        let edge = endpoint.snap.edge;
+        let segment = (graph.tail(edge), graph.head(edge));
+        segment == (a, b) || segment == (b, a)
+    };
+    let snapped = |endpoint: &Endpoint| match endpoint.location {
+        Location::Node(_) => None,
+        Location::Coordinates(..) => Some((endpoint.snap.lat, endpoint.snap.lon)),
+    };
+    let (first, last) = (snapped(from), snapped(to));
+    let start = match nodes[..] {
+        [a, b, ..] if first.is_some() && on_segment(from, a, b) => 1,
+        _ => 0,
+    };
+    let end = match nodes[..] {
+        [.., a, b] if last.is_some() && on_segment(to, a, b) => nodes.len() - 1,
+        _ => nodes.len(),
+    };
+    let coordinates: Vec<String> = first
+        .into_iter()
+        .chain(
+            nodes[start..end.max(start)]
+                .iter()
+                .map(|node| graph.coordinates(*node)),
+        )
+        .chain(last)
+        .map(|(lat, lon)| format!("[{:.7},{:.7}]", lon, lat))
+        .collect();
+    format!("[{}]", coordinates.join(","))
+}
+
+/// Nodes settled by Dijkstra divided by the nodes settled by `algorithm` for `path`, if
+/// Dijkstra is among the other `routes` and found a route too.
+fn speedup(routes: &[(Algorithm, Option<Path>)], algorithm: Algorithm, path: &Path) -> Option<f64> {
+    if algorithm == Algorithm::Dijkstra {
+        return None;
+    }
+    let (_, dijkstra) = routes
+        .iter()
+        .find(|(algorithm, _)| *algorithm == Algorithm::Dijkstra)?;
+    Some(dijkstra.as_ref()?.settled as f64 / path.settled.max(1) as f64)
+}
+
+fn format_routes(
+    format: OutputFormat,
+    map: &Map,
+    from: &Endpoint,
+    to: &Endpoint,
+    routes: &[(Algorithm, Option<Path>)],
+) -> String {
+    let mut output = String::new();
+    match format {
+        OutputFormat::Text => {
+            for (algorithm, path) in routes.iter() {
+                let line = match path {
+                    Some(path) => format!(
+                        "{}: path has length {:.1} m, duration {:.1} s and {} nodes, {} nodes settled{}\n",
+                        algorithm,
+                        path.length,
+                        path.duration,
+                        path.nodes.len(),
+                        path.settled,
+                        speedup(routes, *algorithm, path)
+                            .map_or(String::new(), |speedup| format!(", speedup {:.1}x", speedup))
+                    ),
+                    None => format!(
+                        "{}: there is no path between {} and {}\n",
+                        algorithm, from.location, to.location
+                    ),
+                };
+                output.push_str(&line);
+            }
+        }
+        OutputFormat::Json => {
+            let routes: Vec<String> = routes
+                .iter()
+                .map(|(algorithm, path)| match path {
+                    Some(path) => {
+                        let nodes: Vec<String> =
+                            path.nodes.iter().map(|id| id.0.to_string()).collect();
+                        let speedup = speedup(routes, *algorithm, path)
+                            .map_or(String::new(), |speedup| {
+                                format!(",\"speedup\":{:.2}", speedup)
+                            });
+                        format!(
+                            "{{\"algorithm\":\"{}\",\"length\":{:.1},\"duration\":{:.1},\
+                             \"settled\":{}{},\"nodes\":[{}],\"coordinates\":{}}}",
+                            algorithm,
+                            path.length,
+                            path.duration,
+                            path.settled,
+                            speedup,
+                            nodes.join(","),
+                            path_coordinates(map, path, from, to)
+                        )
+                    }
+                    None => format!("{{\"algorithm\":\"{}\",\"path\":null}}", algorithm),
+                })
+                .collect();
+            output = format!(
+                "{{\"from\":{},\"to\":{},\"routes\":[{}]}}\n",
+                from.json(),
+                to.json(),
+                routes.join(",")
+            );
+        }
+        OutputFormat::GeoJson => {
+            let features: Vec<String> = routes
+                .iter()
+                .filter_map(|(algorithm, path)| {
+                    let path = path.as_ref()?;
+                    Some(format!(
+                        "{{\"type\":\"Feature\",\"properties\":{{\"algorithm\":\"{}\",\
+                         \"length\":{:.1},\"duration\":{:.1},\"settled\":{}}},\
+                         \"geometry\":{{\"type\":\"LineString\",\"coordinates\":{}}}}}",
+                        algorithm,
+                        path.length,
+                        path.duration,
+                        path.settled,
+                        path_coordinates(map, path, from, to)
+                    ))
+                })
+                .collect();
+            output = format!(
+                "{{\"type\":\"FeatureCollection\",\"features\":[{}]}}\n",
+                features.join(",")
+            );
+        }
+    }
+    output
+}
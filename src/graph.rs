@@ -0,0 +1,738 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+use osmpbfreader::NodeId;
+
+use crate::coordinate_distance;
+use crate::restriction::TurnRestrictions;
+use crate::storage::check_indices;
+use crate::storage::check_offsets;
+use crate::storage::corrupt;
+use crate::storage::Decoder;
+use crate::storage::Encode;
+use crate::storage::Encoder;
+use crate::storage::StorageError;
+use crate::NodeInfo;
+
+/// Index of a node in a `Graph`.
+pub type NodeIndex = u32;
+
+/// Index of an edge in a `Graph`, in the order of the out edges.
+pub type EdgeIndex = u32;
+
+/// Marks a missing node in arrays indexed by `NodeIndex`, e.g. the parent of a search root.
+pub const INVALID_NODE: NodeIndex = NodeIndex::MAX;
+
+/// Metric minimised by the routing algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weighting {
+    /// Length in meters, for shortest routes.
+    Distance,
+    /// Travel time in seconds, for fastest routes.
+    Time,
+}
+
+impl FromStr for Weighting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "distance" | "shortest" => Ok(Weighting::Distance),
+            "time" | "fastest" => Ok(Weighting::Time),
+            _ => Err(format!("unknown weighting {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Weighting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Weighting::Distance => "distance",
+            Weighting::Time => "time",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Edge seen from one of its endpoints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeRef {
+    /// The other endpoint, the head of an out edge or the tail of an in edge.
+    pub node: NodeIndex,
+    /// Weight of the edge in the metric of the graph.
+    pub weight: f64,
+    pub id: EdgeIndex,
+}
+
+/// Dense road graph. OSM node ids are remapped to contiguous indices sorted by id, and the
+/// edges are stored in compressed sparse row arrays for both directions.
+#[derive(Debug, Clone)]
+pub struct Graph {
+    /// OSM id of every node.
+    ids: Vec<NodeId>,
+    /// Latitude and longitude of every node in decimicro degrees (10⁻⁷ degrees).
+    coordinates: Vec<(i32, i32)>,
+    weighting: Weighting,
+    /// Largest speed on any edge in m/s, used to bound travel times from below.
+    max_speed: f64,
+    /// Out edges of node `v` are `first_out[v]..first_out[v + 1]`.
+    first_out: Vec<u32>,
+    heads: Vec<NodeIndex>,
+    weights: Vec<f64>,
+    /// Length of every edge in meters.
+    lengths: Vec<f64>,
+    /// Travel time of every edge in seconds.
+    durations: Vec<f64>,
+    /// In edges of node `v` are `first_in[v]..first_in[v + 1]`.
+    first_in: Vec<u32>,
+    tails: Vec<NodeIndex>,
+    in_weights: Vec<f64>,
+    /// Index of every in edge among the out edges.
+    in_ids: Vec<EdgeIndex>,
+    turn_restrictions: TurnRestrictions,
+}
+
+/// Component of nodes without edges.
+pub const NO_COMPONENT: u32 = u32::MAX;
+
+/// Weakly or strongly connected components of a `Graph`, numbered from the largest one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Components {
+    /// Component of every node, `NO_COMPONENT` for nodes without edges.
+    ids: Vec<u32>,
+    /// Number of nodes in every component, from the largest one.
+    sizes: Vec<usize>,
+}
+
+impl Components {
+    /// Renumbers the components given by `labels` and `sizes` from the largest one.
+    fn new(labels: Vec<u32>, sizes: Vec<usize>) -> Self {
+        let mut order: Vec<usize> = (0..sizes.len()).collect();
+        order.sort_by_key(|label| std::cmp::Reverse(sizes[*label]));
+        let mut renumbered = vec![0; sizes.len()];
+        for (id, label) in order.iter().enumerate() {
+            renumbered[*label] = id as u32;
+        }
+        Components {
+            ids: labels
+                .iter()
+                .map(|label| match *label {
+                    NO_COMPONENT => NO_COMPONENT,
+                    label => renumbered[label as usize],
+                })
+                .collect(),
+            sizes: order.iter().map(|label| sizes[*label]).collect(),
+        }
+    }
+
+    /// Component of `node`, `None` if it has no edges.
+    pub fn id(&self, node: NodeIndex) -> Option<u32> {
+        Some(self.ids[node as usize]).filter(|id| *id != NO_COMPONENT)
+    }
+
+    /// Component of every node, `NO_COMPONENT` for nodes without edges.
+    pub fn ids(&self) -> &[u32] {
+        &self.ids
+    }
+
+    /// Number of nodes in every component, sorted from the largest one.
+    pub fn sizes(&self) -> &[usize] {
+        &self.sizes
+    }
+
+    pub fn count(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// Whether every node belongs to the largest component.
+    pub fn largest(&self) -> Vec<bool> {
+        self.ids.iter().map(|id| *id == 0).collect()
+    }
+}
+
+/// Nodes outside the largest strongly connected component that routes from or to it get
+/// stuck at, typically behind oneway edges leading out of the extract.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Traps {
+    /// Nodes reachable from the component without a way back.
+    pub no_exit: Vec<NodeIndex>,
+    /// Nodes the component is reachable from but that cannot be reached from it.
+    pub no_entry: Vec<NodeIndex>,
+}
+
+/// Offsets of the groups of edges starting at every node, given the first node of every edge.
+fn first_edges(node_count: usize, sources: impl Iterator<Item = NodeIndex>) -> Vec<u32> {
+    let mut first = vec![0u32; node_count + 1];
+    for from in sources {
+        first[from as usize + 1] += 1;
+    }
+    for i in 0..node_count {
+        first[i + 1] += first[i];
+    }
+    first
+}
+
+impl Graph {
+    /// Builds the graph over `nodes` with the directed `edges` between them, given as
+    /// `(from, to, speed in km/h)`. Routing algorithms minimise `weighting`.
+    pub fn new(
+        nodes: &HashMap<NodeId, NodeInfo>,
+        edges: &[(NodeId, NodeId, f64)],
+        weighting: Weighting,
+    ) -> Self {
+        let mut ids: Vec<NodeId> = nodes.keys().copied().collect();
+        ids.sort();
+        let index: HashMap<NodeId, NodeIndex> = ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i as NodeIndex))
+            .collect();
+        let coordinates: Vec<(i32, i32)> = ids
+            .iter()
+            .map(|id| (nodes[id].decimicro_lat, nodes[id].decimicro_lon))
+            .collect();
+
+        let forward: Vec<(NodeIndex, NodeIndex, f64, f64)> = edges
+            .iter()
+            .map(|(from, to, speed)| {
+                let (from_info, to_info) = (&nodes[from], &nodes[to]);
+                let length = coordinate_distance(
+                    from_info.lat(),
+                    from_info.lon(),
+                    to_info.lat(),
+                    to_info.lon(),
+                );
+                (index[from], index[to], length, length / (speed / 3.6))
+            })
+            .collect();
+        Graph::from_edges(ids, coordinates, weighting, forward)
+    }
+
+    /// Builds the compressed sparse row arrays of the nodes with `ids` and `coordinates`
+    /// and the edges given as `(from, to, length, duration)`.
+    fn from_edges(
+        ids: Vec<NodeId>,
+        coordinates: Vec<(i32, i32)>,
+        weighting: Weighting,
+        mut forward: Vec<(NodeIndex, NodeIndex, f64, f64)>,
+    ) -> Self {
+        forward.sort_by_key(|(from, to, _, _)| (*from, *to));
+
+        let first_out = first_edges(ids.len(), forward.iter().map(|e| e.0));
+        let heads: Vec<NodeIndex> = forward.iter().map(|e| e.1).collect();
+        let lengths: Vec<f64> = forward.iter().map(|e| e.2).collect();
+        let durations: Vec<f64> = forward.iter().map(|e| e.3).collect();
+        let weights = match weighting {
+            Weighting::Distance => lengths.clone(),
+            Weighting::Time => durations.clone(),
+        };
+        let max_speed = lengths
+            .iter()
+            .zip(durations.iter())
+            .filter(|(_, duration)| **duration > 0.0)
+            .map(|(length, duration)| length / duration)
+            .fold(1.0, f64::max);
+
+        let first_in = first_edges(ids.len(), heads.iter().copied());
+        let mut next = first_in.clone();
+        let mut tails = vec![0; forward.len()];
+        let mut in_weights = vec![0.0; forward.len()];
+        let mut in_ids = vec![0; forward.len()];
+        for (id, (from, to, _, _)) in forward.iter().enumerate() {
+            let pos = next[*to as usize] as usize;
+            tails[pos] = *from;
+            in_weights[pos] = weights[id];
+            in_ids[pos] = id as EdgeIndex;
+            next[*to as usize] += 1;
+        }
+
+        Self {
+            ids,
+            coordinates,
+            weighting,
+            max_speed,
+            first_out,
+            heads,
+            weights,
+            lengths,
+            durations,
+            first_in,
+            tails,
+            in_weights,
+            in_ids,
+            turn_restrictions: TurnRestrictions::default(),
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.heads.len()
+    }
+
+    /// OSM id of the node at `node`.
+    pub fn id(&self, node: NodeIndex) -> NodeId {
+        self.ids[node as usize]
+    }
+
+    /// Index of the node with OSM id `id`, if it is part of the graph.
+    pub fn index(&self, id: NodeId) -> Option<NodeIndex> {
+        self.ids.binary_search(&id).ok().map(|i| i as NodeIndex)
+    }
+
+    /// Latitude and longitude of `node` in degrees.
+    pub fn coordinates(&self, node: NodeIndex) -> (f64, f64) {
+        let (lat, lon) = self.coordinates[node as usize];
+        (lat as f64 * 1e-7, lon as f64 * 1e-7)
+    }
+
+    /// Latitude and longitude of `node` in decimicro degrees.
+    pub fn decimicro_coordinates(&self, node: NodeIndex) -> (i32, i32) {
+        self.coordinates[node as usize]
+    }
+
+    /// Great-circle distance between two nodes in meters.
+    pub fn distance(&self, from: NodeIndex, to: NodeIndex) -> f64 {
+        let (lat1, lon1) = self.coordinates(from);
+        let (lat2, lon2) = self.coordinates(to);
+        coordinate_distance(lat1, lon1, lat2, lon2)
+    }
+
+    pub fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+
+    /// Lower bound on the weight of any path from `from` to `to`, based on the great-circle
+    /// distance and, for travel times, on the largest speed in the graph.
+    pub fn weight_lower_bound(&self, from: NodeIndex, to: NodeIndex) -> f64 {
+        match self.weighting {
+            Weighting::Distance => self.distance(from, to),
+            Weighting::Time => self.distance(from, to) / self.max_speed,
+        }
+    }
+
+    /// Edges leaving `node`.
+    pub fn out_edges(&self, node: NodeIndex) -> impl Iterator<Item = EdgeRef> + '_ {
+        self.edges(node, false)
+    }
+
+    /// Edges entering `node`.
+    pub fn in_edges(&self, node: NodeIndex) -> impl Iterator<Item = EdgeRef> + '_ {
+        self.edges(node, true)
+    }
+
+    /// Edges leaving `node`, or entering it if `reverse` is set.
+    pub fn edges(&self, node: NodeIndex, reverse: bool) -> impl Iterator<Item = EdgeRef> + '_ {
+        let node = node as usize;
+        let range = if reverse {
+            self.first_in[node]..self.first_in[node + 1]
+        } else {
+            self.first_out[node]..self.first_out[node + 1]
+        };
+        range.map(move |pos| {
+            let pos = pos as usize;
+            if reverse {
+                EdgeRef {
+                    node: self.tails[pos],
+                    weight: self.in_weights[pos],
+                    id: self.in_ids[pos],
+                }
+            } else {
+                EdgeRef {
+                    node: self.heads[pos],
+                    weight: self.weights[pos],
+                    id: pos as EdgeIndex,
+                }
+            }
+        })
+    }
+
+    /// Node the edge `edge` starts at.
+    pub fn tail(&self, edge: EdgeIndex) -> NodeIndex {
+        (self.first_out.partition_point(|first| *first <= edge) - 1) as NodeIndex
+    }
+
+    /// Node the edge `edge` ends at.
+    pub fn head(&self, edge: EdgeIndex) -> NodeIndex {
+        self.heads[edge as usize]
+    }
+
+    /// Edge from `from` to `to`, the lightest one if there are several.
+    pub fn find_edge(&self, from: NodeIndex, to: NodeIndex) -> Option<EdgeIndex> {
+        self.out_edges(from)
+            .filter(|edge| edge.node == to)
+            .min_by(|a, b| a.weight.total_cmp(&b.weight))
+            .map(|edge| edge.id)
+    }
+
+    /// Forbidden turns honoured by Dijkstra, A* and ALT queries.
+    pub fn turn_restrictions(&self) -> &TurnRestrictions {
+        &self.turn_restrictions
+    }
+
+    pub fn set_turn_restrictions(&mut self, turn_restrictions: TurnRestrictions) {
+        self.turn_restrictions = turn_restrictions;
+    }
+
+    /// Length of the edge `edge` in meters.
+    pub fn length(&self, edge: EdgeIndex) -> f64 {
+        self.lengths[edge as usize]
+    }
+
+    /// Travel time on the edge `edge` in seconds.
+    pub fn duration(&self, edge: EdgeIndex) -> f64 {
+        self.durations[edge as usize]
+    }
+
+    pub fn degree(&self, node: NodeIndex) -> usize {
+        (self.first_out[node as usize + 1] - self.first_out[node as usize]
+            + self.first_in[node as usize + 1]
+            - self.first_in[node as usize]) as usize
+    }
+
+    /// Weakly connected components among nodes with at least one edge.
+    pub fn components(&self) -> Components {
+        let mut labels = vec![NO_COMPONENT; self.node_count()];
+        let mut to_visit: VecDeque<NodeIndex> = VecDeque::new();
+        let mut sizes = Vec::new();
+
+        for curr in 0..self.node_count() as NodeIndex {
+            if labels[curr as usize] == NO_COMPONENT && self.degree(curr) != 0 {
+                let label = sizes.len() as u32;
+                let mut component_size = 0;
+                to_visit.push_back(curr);
+                labels[curr as usize] = label;
+
+                while let Some(node) = to_visit.pop_front() {
+                    component_size += 1;
+                    for edge in self.out_edges(node).chain(self.in_edges(node)) {
+                        if labels[edge.node as usize] == NO_COMPONENT {
+                            labels[edge.node as usize] = label;
+                            to_visit.push_back(edge.node);
+                        }
+                    }
+                }
+                sizes.push(component_size);
+            }
+        }
+
+        Components::new(labels, sizes)
+    }
+
+    /// Strongly connected components among nodes with at least one edge, found with an
+    /// iterative version of Tarjan's algorithm. Routes exist between any two nodes of the
+    /// same component.
+    pub fn strong_components(&self) -> Components {
+        let node_count = self.node_count();
+        let mut order = vec![u32::MAX; node_count];
+        let mut low = vec![0u32; node_count];
+        let mut on_stack = vec![false; node_count];
+        let mut stack: Vec<NodeIndex> = Vec::new();
+        let mut labels = vec![NO_COMPONENT; node_count];
+        let mut sizes = Vec::new();
+        let mut visited = 0;
+        // Nodes of the depth-first search together with their next out edge.
+        let mut calls: Vec<(NodeIndex, u32)> = Vec::new();
+
+        for root in 0..node_count as NodeIndex {
+            if order[root as usize] != u32::MAX || self.degree(root) == 0 {
+                continue;
+            }
+            let mut entered = Some(root);
+            loop {
+                if let Some(node) = entered.take() {
+                    order[node as usize] = visited;
+                    low[node as usize] = visited;
+                    visited += 1;
+                    stack.push(node);
+                    on_stack[node as usize] = true;
+                    calls.push((node, self.first_out[node as usize]));
+                }
+                let Some((node, edge)) = calls.last().copied() else {
+                    break;
+                };
+                if edge < self.first_out[node as usize + 1] {
+                    calls.last_mut().unwrap().1 += 1;
+                    let head = self.heads[edge as usize];
+                    if order[head as usize] == u32::MAX {
+                        entered = Some(head);
+                    } else if on_stack[head as usize] {
+                        low[node as usize] = low[node as usize].min(order[head as usize]);
+                    }
+                    continue;
+                }
+                calls.pop();
+                if let Some((parent, _)) = calls.last() {
+                    low[*parent as usize] = low[*parent as usize].min(low[node as usize]);
+                }
+                if low[node as usize] == order[node as usize] {
+                    let label = sizes.len() as u32;
+                    let mut component_size = 0;
+                    while let Some(member) = stack.pop() {
+                        on_stack[member as usize] = false;
+                        labels[member as usize] = label;
+                        component_size += 1;
+                        if member == node {
+                            break;
+                        }
+                    }
+                    sizes.push(component_size);
+                }
+            }
+        }
+        Components::new(labels, sizes)
+    }
+
+    /// Nodes reachable from the nodes for which `from` is set, following the edges
+    /// backwards if `reverse` is set.
+    fn reachable(&self, from: &[bool], reverse: bool) -> Vec<bool> {
+        let mut reached = from.to_vec();
+        let mut to_visit: Vec<NodeIndex> = (0..self.node_count() as NodeIndex)
+            .filter(|node| from[*node as usize])
+            .collect();
+        while let Some(node) = to_visit.pop() {
+            for edge in self.edges(node, reverse) {
+                if !reached[edge.node as usize] {
+                    reached[edge.node as usize] = true;
+                    to_visit.push(edge.node);
+                }
+            }
+        }
+        reached
+    }
+
+    /// Nodes outside the largest of the `strong` components, by how they are connected to
+    /// it.
+    pub fn traps(&self, strong: &Components) -> Traps {
+        let largest = strong.largest();
+        let forward = self.reachable(&largest, false);
+        let backward = self.reachable(&largest, true);
+        let outside = |reached: &[bool]| -> Vec<NodeIndex> {
+            (0..self.node_count() as NodeIndex)
+                .filter(|node| reached[*node as usize] && !largest[*node as usize])
+                .collect()
+        };
+        Traps {
+            no_exit: outside(&forward),
+            no_entry: outside(&backward),
+        }
+    }
+
+    /// Sizes of the weakly connected components among nodes with at least one edge, from
+    /// the largest one.
+    pub fn component_sizes(&self) -> Vec<usize> {
+        self.components().sizes
+    }
+
+    /// Graph induced by the nodes for which `keep` is set, with the edges between them and
+    /// the forbidden turns using only those edges. Nodes and edges keep their relative
+    /// order, so their indices only shift down.
+    pub fn subgraph(&self, keep: &[bool]) -> Graph {
+        let mut new_index = vec![INVALID_NODE; self.node_count()];
+        let mut ids = Vec::new();
+        let mut coordinates = Vec::new();
+        for node in 0..self.node_count() {
+            if keep[node] {
+                new_index[node] = ids.len() as NodeIndex;
+                ids.push(self.ids[node]);
+                coordinates.push(self.coordinates[node]);
+            }
+        }
+        let mut new_edge = vec![None; self.edge_count()];
+        let mut forward = Vec::new();
+        for tail in 0..self.node_count() as NodeIndex {
+            for edge in self.out_edges(tail) {
+                let (from, to) = (new_index[tail as usize], new_index[edge.node as usize]);
+                if from != INVALID_NODE && to != INVALID_NODE {
+                    new_edge[edge.id as usize] = Some(forward.len() as EdgeIndex);
+                    forward.push((from, to, self.length(edge.id), self.duration(edge.id)));
+                }
+            }
+        }
+        let mut graph = Graph::from_edges(ids, coordinates, self.weighting, forward);
+        graph.turn_restrictions = self.turn_restrictions.remap(|edge| new_edge[edge as usize]);
+        graph
+    }
+}
+
+impl Encode for Graph {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.ids.encode(encoder)?;
+        self.coordinates.encode(encoder)?;
+        self.weighting.encode(encoder)?;
+        self.max_speed.encode(encoder)?;
+        self.first_out.encode(encoder)?;
+        self.heads.encode(encoder)?;
+        self.weights.encode(encoder)?;
+        self.lengths.encode(encoder)?;
+        self.durations.encode(encoder)?;
+        self.first_in.encode(encoder)?;
+        self.tails.encode(encoder)?;
+        self.in_weights.encode(encoder)?;
+        self.in_ids.encode(encoder)?;
+        self.turn_restrictions.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        let graph = Graph {
+            ids: Encode::decode(decoder)?,
+            coordinates: Encode::decode(decoder)?,
+            weighting: Encode::decode(decoder)?,
+            max_speed: Encode::decode(decoder)?,
+            first_out: Encode::decode(decoder)?,
+            heads: Encode::decode(decoder)?,
+            weights: Encode::decode(decoder)?,
+            lengths: Encode::decode(decoder)?,
+            durations: Encode::decode(decoder)?,
+            first_in: Encode::decode(decoder)?,
+            tails: Encode::decode(decoder)?,
+            in_weights: Encode::decode(decoder)?,
+            in_ids: Encode::decode(decoder)?,
+            turn_restrictions: Encode::decode(decoder)?,
+        };
+
+        let (node_count, edge_count) = (graph.ids.len(), graph.heads.len());
+        if graph.coordinates.len() != node_count
+            || [
+                &graph.weights,
+                &graph.lengths,
+                &graph.durations,
+                &graph.in_weights,
+            ]
+            .iter()
+            .any(|values| values.len() != edge_count)
+            || graph.tails.len() != edge_count
+            || graph.in_ids.len() != edge_count
+            || graph.ids.windows(2).any(|pair| pair[0] >= pair[1])
+        {
+            return Err(corrupt("inconsistent graph arrays"));
+        }
+        check_offsets(&graph.first_out, node_count, edge_count)?;
+        check_offsets(&graph.first_in, node_count, edge_count)?;
+        check_indices(
+            graph.heads.iter().chain(graph.tails.iter()).copied(),
+            node_count,
+        )?;
+        check_indices(graph.in_ids.iter().copied(), edge_count)?;
+        check_indices(graph.turn_restrictions.edges(), edge_count)?;
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use osmpbfreader::NodeId;
+    use osmpbfreader::WayId;
+
+    use super::Graph;
+    use super::Traps;
+    use super::Weighting;
+    use crate::map::fixture;
+    use crate::profile::Profile;
+    use crate::restriction::RestrictionKind;
+    use crate::restriction::TurnRestriction;
+    use crate::restriction::Via;
+    use crate::restriction::UNRESTRICTED;
+    use crate::Map;
+
+    /// One way cycle 1, 2, 3 with a one way tail 3, 4, 5 leading out of it and a one way edge
+    /// from 6 leading into it, a two way edge between 7 and 8, and node 9 without edges. Node
+    /// `n` has the index `n - 1`.
+    fn cycle_with_tails() -> Graph {
+        let coordinates: Vec<(f64, f64)> = (0..9).map(|i| (0.0, i as f64 * 0.001)).collect();
+        let edges: Vec<(NodeId, NodeId, f64)> = [
+            (1, 2),
+            (2, 3),
+            (3, 1),
+            (3, 4),
+            (4, 5),
+            (6, 1),
+            (7, 8),
+            (8, 7),
+        ]
+        .iter()
+        .map(|(a, b)| (NodeId(*a), NodeId(*b), 50.0))
+        .collect();
+        Graph::new(&fixture::nodes(&coordinates), &edges, Weighting::Distance)
+    }
+
+    #[test]
+    fn weak_components() {
+        let components = cycle_with_tails().components();
+        assert_eq!(components.sizes(), &[6, 2]);
+        assert!((0..6).all(|node| components.id(node) == Some(0)));
+        assert_eq!(components.id(6), Some(1));
+        assert_eq!(components.id(7), Some(1));
+        assert_eq!(components.id(8), None);
+    }
+
+    #[test]
+    fn strong_components_and_traps() {
+        let graph = cycle_with_tails();
+        let strong = graph.strong_components();
+        assert_eq!(strong.sizes(), &[3, 2, 1, 1, 1]);
+        let largest = [true, true, true, false, false, false, false, false, false];
+        assert_eq!(strong.largest(), largest);
+        assert_eq!(strong.id(6), strong.id(7));
+        assert_eq!(strong.id(8), None);
+        let singletons: Vec<Option<u32>> = [3, 4, 5].iter().map(|node| strong.id(*node)).collect();
+        assert!(singletons.iter().all(|id| id.is_some_and(|id| id >= 2)));
+        assert_ne!(singletons[0], singletons[1]);
+        assert_eq!(
+            graph.traps(&strong),
+            Traps {
+                no_exit: vec![3, 4],
+                no_entry: vec![5],
+            }
+        );
+    }
+
+    #[test]
+    fn subgraph_remaps_turn_restrictions() {
+        // Crossing at node 2 of ways 10 from node 3 in the west, 11 to node 4 in the east, 12
+        // to node 5 in the north and 13 from node 1 in the south.
+        let nodes = fixture::nodes(&[
+            (-0.001, 0.0),
+            (0.0, 0.0),
+            (0.0, -0.001),
+            (0.0, 0.001),
+            (0.001, 0.0),
+        ]);
+        let ways = fixture::ways(&[(10, &[3, 2]), (11, &[2, 4]), (12, &[2, 5]), (13, &[1, 2])]);
+        let restriction = |from: i64, to: i64| TurnRestriction {
+            kind: RestrictionKind::No,
+            from: WayId(from),
+            via: Via::Node(NodeId(2)),
+            to: WayId(to),
+        };
+        let restrictions = [restriction(10, 12), restriction(13, 11)];
+        let map = Map::new(
+            nodes,
+            ways,
+            &restrictions,
+            Profile::Car,
+            Weighting::Distance,
+        );
+        assert_eq!(map.graph().turn_restrictions().sequence_count(), 2);
+
+        // Removing node 1 shifts the indices of all other nodes and edges.
+        let keep: Vec<bool> = (0..5).map(|node| node != 0).collect();
+        let graph = map.graph().subgraph(&keep);
+        assert_eq!(graph.node_count(), 4);
+        let restrictions = graph.turn_restrictions();
+        assert_eq!(restrictions.sequence_count(), 1);
+        let edge = |from: i64, to: i64| {
+            let index = |id| graph.index(NodeId(id)).unwrap();
+            graph.find_edge(index(from), index(to)).unwrap()
+        };
+        let state = restrictions.next(UNRESTRICTED, edge(3, 2)).unwrap();
+        assert_eq!(restrictions.next(state, edge(2, 5)), None);
+        assert!(restrictions.next(state, edge(2, 4)).is_some());
+    }
+}
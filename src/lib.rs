@@ -0,0 +1,58 @@
+//! Shortest path routing on OpenStreetMap road networks.
+//!
+//! `load_map` reads a PBF extract into a `Map`, whose `Graph` is queried with Dijkstra, A*,
+//! their bidirectional variants, ALT or a `ContractionHierarchy`, either directly or through
+//! a `Router`, which also snaps coordinates to the nearest road with a `SpatialIndex`.
+//! `MapDrawing` shows the road network in an SDL window, where routes are picked with the
+//! mouse and their searches animated through a `SearchObserver`, or saves it with routes
+//! and overlays to a PNG without a window.
+
+pub mod alt;
+pub mod ch;
+pub mod graph;
+mod loader;
+mod map;
+pub mod maxspeed;
+pub mod profile;
+pub mod projection;
+mod render;
+pub mod restriction;
+pub mod router;
+pub mod routing;
+pub mod snap;
+pub mod storage;
+
+pub use loader::load_map;
+pub use loader::DanglingPolicy;
+pub use loader::LoadError;
+pub use loader::LoadReport;
+pub use map::BoundingBox;
+pub use map::Map;
+pub use map::NodeInfo;
+pub use map::Oneway;
+pub use map::Prune;
+pub use map::WayInfo;
+pub use render::MapDrawing;
+pub use render::Overlay;
+pub use render::Picture;
+
+const EARTH_RADIUS: f64 = 6371.0;
+
+fn deg2rad(deg: f64) -> f64 {
+    std::f64::consts::PI * deg / 180.0
+}
+
+// https://github.com/Aj0SK/mymap/blob/master/src/earthfunctions.h
+pub fn coordinate_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let lat1 = deg2rad(lat1);
+    let lon1 = deg2rad(lon1);
+    let lat2 = deg2rad(lat2);
+    let lon2 = deg2rad(lon2);
+
+    let d_lat = (lat1 - lat2).abs();
+    let d_lon = (lon1 - lon2).abs();
+
+    let a = (d_lat / 2.0).sin().powf(2.0) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powf(2.0);
+    let d_sigma = 2.0 * a.sqrt().asin();
+    EARTH_RADIUS * d_sigma * 1000.0
+}
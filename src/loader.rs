@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
+
+use osmpbfreader::NodeId;
+use osmpbfreader::OsmObj;
+use osmpbfreader::WayId;
+use rayon::prelude::*;
+
+use crate::graph::Weighting;
+use crate::profile::Profile;
+use crate::restriction::TurnRestriction;
+use crate::Map;
+use crate::NodeInfo;
+use crate::WayInfo;
+
+/// Error while loading a map from a PBF file.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file could not be opened or read.
+    Io(io::Error),
+    /// The file is not a valid PBF file.
+    Pbf(osmpbfreader::Error),
+    /// A way references a node that is not in the file, typical for clipped extracts.
+    MissingNode { way: WayId, node: NodeId },
+    /// A way has less than two nodes.
+    EmptyWay(WayId),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "I/O error: {}", err),
+            LoadError::Pbf(err) => write!(f, "PBF decoding error: {}", err),
+            LoadError::MissingNode { way, node } => {
+                write!(f, "way {} references missing node {}", way.0, node.0)
+            }
+            LoadError::EmptyWay(way) => write!(f, "way {} has less than two nodes", way.0),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::Pbf(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<osmpbfreader::Error> for LoadError {
+    fn from(err: osmpbfreader::Error) -> Self {
+        match err {
+            osmpbfreader::Error::Io(err) => LoadError::Io(err),
+            err => LoadError::Pbf(err),
+        }
+    }
+}
+
+/// What to do with ways referencing nodes missing from the file, and with ways that are too
+/// short to form an edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DanglingPolicy {
+    /// Fail with `LoadError::MissingNode` or `LoadError::EmptyWay`.
+    Fail,
+    /// Drop the whole way.
+    Skip,
+    /// Keep the longest run of consecutive nodes present in the file, which is the part of
+    /// the way inside a clipped extract.
+    Truncate,
+}
+
+impl FromStr for DanglingPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(DanglingPolicy::Fail),
+            "skip" => Ok(DanglingPolicy::Skip),
+            "truncate" => Ok(DanglingPolicy::Truncate),
+            _ => Err(format!("unknown dangling reference policy {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for DanglingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DanglingPolicy::Fail => "fail",
+            DanglingPolicy::Skip => "skip",
+            DanglingPolicy::Truncate => "truncate",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Ways changed by the `DanglingPolicy` and time spent in every phase while loading.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Ways left out because of missing nodes or because they were too short.
+    pub dropped_ways: usize,
+    /// Ways shortened to the part whose nodes are in the file.
+    pub truncated_ways: usize,
+    /// Name and duration of every loading phase, in the order they ran.
+    pub phases: Vec<(&'static str, Duration)>,
+}
+
+/// Longest run of consecutive nodes of `way` that are in `nodes`.
+fn longest_present_run<'a>(way: &'a WayInfo, nodes: &HashMap<NodeId, NodeInfo>) -> &'a [NodeId] {
+    way.nodes
+        .split(|id| !nodes.contains_key(id))
+        .max_by_key(|run| run.len())
+        .unwrap_or(&[])
+}
+
+/// Applies `policy` to the ways with missing nodes or less than two nodes. Returns the
+/// number of dropped and truncated ways.
+fn check_ways(
+    ways: &mut HashMap<WayId, WayInfo>,
+    nodes: &HashMap<NodeId, NodeInfo>,
+    policy: DanglingPolicy,
+) -> Result<(usize, usize), LoadError> {
+    let mut truncated_ways = 0;
+    let mut dropped: Vec<WayId> = Vec::new();
+    for (id, way) in ways.iter_mut() {
+        if let Some(node) = way.nodes.iter().find(|node| !nodes.contains_key(node)) {
+            match policy {
+                DanglingPolicy::Fail => {
+                    return Err(LoadError::MissingNode {
+                        way: *id,
+                        node: *node,
+                    })
+                }
+                DanglingPolicy::Skip => {
+                    dropped.push(*id);
+                    continue;
+                }
+                DanglingPolicy::Truncate => {
+                    way.nodes = longest_present_run(way, nodes).to_vec();
+                    if way.nodes.len() >= 2 {
+                        truncated_ways += 1;
+                    }
+                }
+            }
+        }
+        if way.nodes.len() < 2 {
+            if policy == DanglingPolicy::Fail {
+                return Err(LoadError::EmptyWay(*id));
+            }
+            dropped.push(*id);
+        }
+    }
+    for id in dropped.iter() {
+        ways.remove(id);
+    }
+    Ok((dropped.len(), truncated_ways))
+}
+
+/// Reads the ways usable with `profile` from the PBF file at `path`, together with their
+/// nodes and the turn restrictions applying to the profile, and builds the `Map` weighted by
+/// `weighting`. Ways referencing nodes that are not in the file are handled by `policy`.
+///
+/// The file is read twice with its blocks decoded in parallel, first for the ways and
+/// relations and then for the nodes of the selected ways, which precede the ways in the file.
+pub fn load_map(
+    path: &Path,
+    profile: Profile,
+    weighting: Weighting,
+    policy: DanglingPolicy,
+) -> Result<(Map, LoadReport), LoadError> {
+    let mut report = LoadReport::default();
+    let f = File::open(path)?;
+    let mut pbf = osmpbfreader::OsmPbfReader::new(f);
+
+    let start = Instant::now();
+    let mut ways: HashMap<WayId, WayInfo> = HashMap::new();
+    let mut restrictions: Vec<TurnRestriction> = Vec::new();
+    for obj in pbf.par_iter() {
+        match obj? {
+            OsmObj::Way(way) => {
+                if profile.accepts(&way.tags) {
+                    ways.insert(way.id, WayInfo::from(way));
+                }
+            }
+            OsmObj::Relation(relation) => {
+                if let Some(restriction) = TurnRestriction::from_relation(&relation, profile) {
+                    restrictions.push(restriction);
+                }
+            }
+            OsmObj::Node(_) => {}
+        }
+    }
+    report.phases.push(("ways", start.elapsed()));
+
+    let start = Instant::now();
+    let mut used_ids: Vec<NodeId> = ways
+        .par_iter()
+        .flat_map_iter(|(_, way)| way.nodes.iter().copied())
+        .collect();
+    used_ids.par_sort_unstable();
+    used_ids.dedup();
+    used_ids.shrink_to_fit();
+    report.phases.push(("node ids", start.elapsed()));
+
+    let start = Instant::now();
+    pbf.rewind()?;
+    let mut nodes: HashMap<NodeId, NodeInfo> = HashMap::with_capacity(used_ids.len());
+    for obj in pbf.par_iter() {
+        if let OsmObj::Node(node) = obj? {
+            if used_ids.binary_search(&node.id).is_ok() {
+                nodes.insert(node.id, NodeInfo::from(node));
+            }
+        }
+    }
+    drop(used_ids);
+    report.phases.push(("nodes", start.elapsed()));
+
+    let (dropped_ways, truncated_ways) = check_ways(&mut ways, &nodes, policy)?;
+    report.dropped_ways = dropped_ways;
+    report.truncated_ways = truncated_ways;
+    nodes.shrink_to_fit();
+    ways.shrink_to_fit();
+
+    let start = Instant::now();
+    let map = Map::new(nodes, ways, &restrictions, profile, weighting);
+    report.phases.push(("graph", start.elapsed()));
+    Ok((map, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use osmpbfreader::NodeId;
+    use osmpbfreader::WayId;
+
+    use super::check_ways;
+    use super::DanglingPolicy;
+    use super::LoadError;
+    use crate::map::fixture;
+    use crate::WayInfo;
+
+    /// Complete way 10, way 11 with a missing node in the middle, way 12 with only one of its
+    /// nodes present and way 13 with a single node. Nodes 1 to 6 are present.
+    fn ways_with_gaps() -> HashMap<WayId, WayInfo> {
+        fixture::ways(&[
+            (10, &[1, 2, 3]),
+            (11, &[1, 2, 99, 3, 4, 5]),
+            (12, &[99, 1, 98]),
+            (13, &[4]),
+        ])
+    }
+
+    fn way_nodes(ways: &HashMap<WayId, WayInfo>) -> HashMap<i64, Vec<i64>> {
+        ways.iter()
+            .map(|(id, way)| (id.0, way.nodes.iter().map(|node| node.0).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn fail_on_missing_nodes_and_short_ways() {
+        let nodes = fixture::nodes(&[(0.0, 0.0); 6]);
+        let mut ways = ways_with_gaps();
+        ways.retain(|id, _| [10, 11].contains(&id.0));
+        let result = check_ways(&mut ways, &nodes, DanglingPolicy::Fail);
+        assert!(matches!(
+            result,
+            Err(LoadError::MissingNode {
+                way: WayId(11),
+                node: NodeId(99)
+            })
+        ));
+
+        let mut ways = ways_with_gaps();
+        ways.retain(|id, _| [10, 13].contains(&id.0));
+        let result = check_ways(&mut ways, &nodes, DanglingPolicy::Fail);
+        assert!(matches!(result, Err(LoadError::EmptyWay(WayId(13)))));
+
+        let mut ways = ways_with_gaps();
+        ways.retain(|id, _| id.0 == 10);
+        let result = check_ways(&mut ways, &nodes, DanglingPolicy::Fail);
+        assert_eq!(result.unwrap(), (0, 0));
+        assert_eq!(way_nodes(&ways), HashMap::from([(10, vec![1, 2, 3])]));
+    }
+
+    #[test]
+    fn skip_drops_incomplete_ways() {
+        let nodes = fixture::nodes(&[(0.0, 0.0); 6]);
+        let mut ways = ways_with_gaps();
+        let result = check_ways(&mut ways, &nodes, DanglingPolicy::Skip);
+        assert_eq!(result.unwrap(), (3, 0));
+        assert_eq!(way_nodes(&ways), HashMap::from([(10, vec![1, 2, 3])]));
+    }
+
+    #[test]
+    fn truncate_keeps_longest_present_run() {
+        let nodes = fixture::nodes(&[(0.0, 0.0); 6]);
+        let mut ways = ways_with_gaps();
+        let result = check_ways(&mut ways, &nodes, DanglingPolicy::Truncate);
+        assert_eq!(result.unwrap(), (2, 1));
+        let expected = HashMap::from([(10, vec![1, 2, 3]), (11, vec![3, 4, 5])]);
+        assert_eq!(way_nodes(&ways), expected);
+    }
+}
@@ -0,0 +1,618 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+use osmpbfreader::Node;
+use osmpbfreader::NodeId;
+use osmpbfreader::Way;
+use osmpbfreader::WayId;
+
+use crate::graph::Graph;
+use crate::graph::NodeIndex;
+use crate::graph::Weighting;
+use crate::profile::Profile;
+use crate::restriction::TurnRestriction;
+use crate::restriction::TurnRestrictions;
+use crate::storage::corrupt;
+use crate::storage::Decoder;
+use crate::storage::Encode;
+use crate::storage::Encoder;
+use crate::storage::StorageError;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+pub struct NodeInfo {
+    /// The tags of the node.
+    pub tags: osmpbfreader::Tags,
+    /// The latitude in decimicro degrees (10⁻⁷ degrees).
+    pub decimicro_lat: i32,
+    /// The longitude in decimicro degrees (10⁻⁷ degrees).
+    pub decimicro_lon: i32,
+}
+
+impl From<&Node> for NodeInfo {
+    fn from(n: &Node) -> Self {
+        NodeInfo {
+            tags: n.tags.clone(),
+            decimicro_lat: n.decimicro_lat,
+            decimicro_lon: n.decimicro_lon,
+        }
+    }
+}
+
+impl From<Node> for NodeInfo {
+    fn from(n: Node) -> Self {
+        NodeInfo {
+            tags: n.tags,
+            decimicro_lat: n.decimicro_lat,
+            decimicro_lon: n.decimicro_lon,
+        }
+    }
+}
+
+impl NodeInfo {
+    /// Returns the latitude of the node in degrees.
+    pub fn lat(&self) -> f64 {
+        self.decimicro_lat as f64 * 1e-7
+    }
+    /// Returns the longitude of the node in degrees.
+    pub fn lon(&self) -> f64 {
+        self.decimicro_lon as f64 * 1e-7
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+pub struct WayInfo {
+    /// The tags of the way.
+    pub tags: osmpbfreader::Tags,
+    /// The ordered list of nodes as id.
+    pub nodes: Vec<osmpbfreader::NodeId>,
+}
+
+impl From<Way> for WayInfo {
+    fn from(n: Way) -> Self {
+        WayInfo {
+            tags: n.tags,
+            nodes: n.nodes,
+        }
+    }
+}
+
+impl From<&Way> for WayInfo {
+    fn from(n: &Way) -> Self {
+        WayInfo {
+            tags: n.tags.clone(),
+            nodes: n.nodes.clone(),
+        }
+    }
+}
+
+/// Directions in which a way can be traversed, relative to the order of its nodes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Oneway {
+    No,
+    Forward,
+    Backward,
+    /// Neither direction is usable.
+    Closed,
+}
+
+impl WayInfo {
+    /// Direction of travel given by the `oneway` tag, or implied by roundabouts and motorways.
+    /// Reversible ways change their direction during the day, so they are not routable at all.
+    pub fn oneway(&self) -> Oneway {
+        match self.tags.get("oneway").map(|v| v.as_str()) {
+            Some("yes") | Some("true") | Some("1") => Oneway::Forward,
+            Some("-1") | Some("reverse") => Oneway::Backward,
+            Some("reversible") | Some("alternating") => Oneway::Closed,
+            Some("no") | Some("false") | Some("0") => Oneway::No,
+            _ => {
+                if self.tags.contains("junction", "roundabout")
+                    || self.tags.contains("junction", "circular")
+                    || self.tags.contains("highway", "motorway")
+                {
+                    Oneway::Forward
+                } else {
+                    Oneway::No
+                }
+            }
+        }
+    }
+}
+
+/// Region between two parallels and two meridians, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+impl BoundingBox {
+    /// Smallest box containing all `points` given as latitude and longitude, `None` if there
+    /// are none.
+    pub fn enclosing(points: impl IntoIterator<Item = (f64, f64)>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let (lat, lon) = points.next()?;
+        let mut region = BoundingBox {
+            min_lat: lat,
+            min_lon: lon,
+            max_lat: lat,
+            max_lon: lon,
+        };
+        for (lat, lon) in points {
+            region.min_lat = region.min_lat.min(lat);
+            region.min_lon = region.min_lon.min(lon);
+            region.max_lat = region.max_lat.max(lat);
+            region.max_lon = region.max_lon.max(lon);
+        }
+        Some(region)
+    }
+
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        (self.min_lat..=self.max_lat).contains(&lat) && (self.min_lon..=self.max_lon).contains(&lon)
+    }
+}
+
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    /// Parses `min_lat,min_lon,max_lat,max_lon`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid bounding box {}", s);
+        let values: Vec<f64> = s
+            .split(',')
+            .map(|value| value.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        let [min_lat, min_lon, max_lat, max_lon] = values[..] else {
+            return Err(invalid());
+        };
+        if min_lat > max_lat || min_lon > max_lon {
+            return Err(invalid());
+        }
+        Ok(BoundingBox {
+            min_lat,
+            min_lon,
+            max_lat,
+            max_lon,
+        })
+    }
+}
+
+/// Connected components kept after loading the map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prune {
+    None,
+    Largest,
+    /// The largest strongly connected component.
+    Strong,
+    /// Components with a node inside the box.
+    Region(BoundingBox),
+}
+
+impl FromStr for Prune {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Prune::None),
+            "largest" => Ok(Prune::Largest),
+            "strong" => Ok(Prune::Strong),
+            _ => s.parse().map(Prune::Region),
+        }
+    }
+}
+
+impl fmt::Display for Prune {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Prune::None => write!(f, "none"),
+            Prune::Largest => write!(f, "largest"),
+            Prune::Strong => write!(f, "strong"),
+            Prune::Region(region) => write!(
+                f,
+                "{},{},{},{}",
+                region.min_lat, region.min_lon, region.max_lat, region.max_lon
+            ),
+        }
+    }
+}
+
+/// Road network usable with a profile, with its routing graph.
+#[derive(Debug, Clone)]
+pub struct Map {
+    ways: HashMap<WayId, WayInfo>,
+    graph: Graph,
+    profile: Profile,
+    restriction_count: usize,
+}
+
+impl Map {
+    /// Builds the directed road graph of the `ways` usable with `profile`, with edges in the
+    /// directions the profile allows on them, weighted by `weighting`. Ways with less than
+    /// two nodes are left out. The `restrictions` are turned into forbidden edge sequences
+    /// of the graph.
+    pub fn new(
+        nodes: HashMap<NodeId, NodeInfo>,
+        mut ways: HashMap<WayId, WayInfo>,
+        restrictions: &[TurnRestriction],
+        profile: Profile,
+        weighting: Weighting,
+    ) -> Self {
+        ways.retain(|_, way_info| way_info.nodes.len() >= 2 && profile.accepts(&way_info.tags));
+        let mut edges: Vec<(NodeId, NodeId, f64)> = Vec::new();
+        for way_info in ways.values() {
+            let oneway = profile.oneway(way_info);
+            let forward_speed = profile.travel_speed(&way_info.tags, true).unwrap();
+            let backward_speed = profile.travel_speed(&way_info.tags, false).unwrap();
+            for segment in way_info.nodes.windows(2) {
+                if oneway == Oneway::No || oneway == Oneway::Forward {
+                    edges.push((segment[0], segment[1], forward_speed));
+                }
+                if oneway == Oneway::No || oneway == Oneway::Backward {
+                    edges.push((segment[1], segment[0], backward_speed));
+                }
+            }
+        }
+        let mut graph = Graph::new(&nodes, &edges, weighting);
+        let turn_restrictions = TurnRestrictions::new(restrictions, &ways, &graph);
+        graph.set_turn_restrictions(turn_restrictions);
+        Self {
+            ways,
+            graph,
+            profile,
+            restriction_count: restrictions.len(),
+        }
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    pub fn ways(&self) -> &HashMap<WayId, WayInfo> {
+        &self.ways
+    }
+
+    pub fn way_count(&self) -> usize {
+        self.ways.len()
+    }
+
+    /// Number of turn restrictions the map was built with.
+    pub fn restriction_count(&self) -> usize {
+        self.restriction_count
+    }
+
+    /// Removes the nodes for which `keep` is not set, together with their edges. Ways are
+    /// cut into their runs of kept nodes. The first run keeps the id of the way, further
+    /// runs get new negative ids, which OSM data does not use. Returns the number of
+    /// removed nodes.
+    fn retain_nodes(&mut self, keep: &[bool]) -> usize {
+        let graph = &self.graph;
+        let kept = |id: &NodeId| graph.index(*id).is_some_and(|node| keep[node as usize]);
+        // Sorted so that the new ids do not depend on the iteration order of the map.
+        let mut way_ids: Vec<WayId> = self.ways.keys().copied().collect();
+        way_ids.sort_unstable();
+        let mut next_id = way_ids.first().map_or(0, |id| id.0.min(0));
+        for way_id in way_ids {
+            if self.ways[&way_id].nodes.iter().all(kept) {
+                continue;
+            }
+            let way = self.ways.remove(&way_id).unwrap();
+            let runs = way.nodes.split(|id| !kept(id)).filter(|run| run.len() >= 2);
+            for (part, nodes) in runs.enumerate() {
+                let id = if part == 0 {
+                    way_id
+                } else {
+                    next_id -= 1;
+                    WayId(next_id)
+                };
+                let part = WayInfo {
+                    tags: way.tags.clone(),
+                    nodes: nodes.to_vec(),
+                };
+                self.ways.insert(id, part);
+            }
+        }
+        let removed = keep.iter().filter(|keep| !**keep).count();
+        self.graph = self.graph.subgraph(keep);
+        removed
+    }
+
+    /// Prunes the map to the components selected by `prune`. Returns the number of removed
+    /// nodes.
+    pub fn prune(&mut self, prune: Prune) -> usize {
+        match prune {
+            Prune::None => 0,
+            Prune::Largest => self.retain_largest_component(),
+            Prune::Strong => self.retain_largest_strong_component(),
+            Prune::Region(region) => self.retain_components_in(&region),
+        }
+    }
+
+    /// Prunes the map to the largest weakly connected component of its graph, leaving out
+    /// islands such as private driveways or fragments cut off by the extract boundary.
+    /// Returns the number of removed nodes.
+    pub fn retain_largest_component(&mut self) -> usize {
+        let keep = self.graph.components().largest();
+        self.retain_nodes(&keep)
+    }
+
+    /// Prunes the map to the largest strongly connected component of its graph, so that
+    /// there is a route between any two of the remaining nodes. Returns the number of
+    /// removed nodes.
+    pub fn retain_largest_strong_component(&mut self) -> usize {
+        let keep = self.graph.strong_components().largest();
+        self.retain_nodes(&keep)
+    }
+
+    /// Prunes the map to the weakly connected components with a node in `region`. Returns
+    /// the number of removed nodes.
+    pub fn retain_components_in(&mut self, region: &BoundingBox) -> usize {
+        let components = self.graph.components();
+        let mut touching = vec![false; components.count()];
+        for node in 0..self.graph.node_count() as NodeIndex {
+            let (lat, lon) = self.graph.coordinates(node);
+            if let Some(id) = components.id(node) {
+                touching[id as usize] |= region.contains(lat, lon);
+            }
+        }
+        let keep: Vec<bool> = (0..self.graph.node_count() as NodeIndex)
+            .map(|node| components.id(node).is_some_and(|id| touching[id as usize]))
+            .collect();
+        self.retain_nodes(&keep)
+    }
+}
+
+impl Encode for WayInfo {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.tags.encode(encoder)?;
+        self.nodes.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        Ok(WayInfo {
+            tags: Encode::decode(decoder)?,
+            nodes: Encode::decode(decoder)?,
+        })
+    }
+}
+
+impl Encode for Map {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.profile.encode(encoder)?;
+        self.restriction_count.encode(encoder)?;
+        self.ways.len().encode(encoder)?;
+        for (id, way) in self.ways.iter() {
+            id.encode(encoder)?;
+            way.encode(encoder)?;
+        }
+        self.graph.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        let profile = Profile::decode(decoder)?;
+        let restriction_count = usize::decode(decoder)?;
+        let ways: Vec<(WayId, WayInfo)> = Encode::decode(decoder)?;
+        let graph = Graph::decode(decoder)?;
+        // Drawing relies on every way having a segment.
+        if ways.iter().any(|(_, way)| way.nodes.len() < 2) {
+            return Err(corrupt("way with less than two nodes"));
+        }
+        if ways
+            .iter()
+            .flat_map(|(_, way)| way.nodes.iter())
+            .any(|id| graph.index(*id).is_none())
+        {
+            return Err(corrupt("way with a node missing from the graph"));
+        }
+        Ok(Map {
+            ways: ways.into_iter().collect(),
+            graph,
+            profile,
+            restriction_count,
+        })
+    }
+}
+
+/// Builders of small hand-made maps for the tests of several modules.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use std::collections::HashMap;
+
+    use osmpbfreader::NodeId;
+    use osmpbfreader::WayId;
+
+    use super::NodeInfo;
+    use super::WayInfo;
+    use crate::graph::Graph;
+    use crate::graph::Weighting;
+
+    /// Untagged nodes numbered from 1, node `n` at `coordinates[n - 1]` given as latitude and
+    /// longitude in degrees.
+    pub(crate) fn nodes(coordinates: &[(f64, f64)]) -> HashMap<NodeId, NodeInfo> {
+        coordinates
+            .iter()
+            .enumerate()
+            .map(|(i, (lat, lon))| {
+                let info = NodeInfo {
+                    tags: osmpbfreader::Tags::new(),
+                    decimicro_lat: (lat * 1e7).round() as i32,
+                    decimicro_lon: (lon * 1e7).round() as i32,
+                };
+                (NodeId(i as i64 + 1), info)
+            })
+            .collect()
+    }
+
+    /// Way without nodes with the tags given as `key=value`.
+    pub(crate) fn tagged_way(tags: &[&str]) -> WayInfo {
+        let tags = tags
+            .iter()
+            .map(|tag| {
+                let (key, value) = tag.split_once('=').unwrap();
+                (key.into(), value.into())
+            })
+            .collect();
+        WayInfo {
+            tags,
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Residential roads given by their ids and nodes.
+    pub(crate) fn ways(ways: &[(i64, &[i64])]) -> HashMap<WayId, WayInfo> {
+        ways.iter()
+            .map(|(id, nodes)| {
+                let mut tags = osmpbfreader::Tags::new();
+                tags.insert("highway".into(), "residential".into());
+                let nodes = nodes.iter().map(|node| NodeId(*node)).collect();
+                (WayId(*id), WayInfo { tags, nodes })
+            })
+            .collect()
+    }
+
+    /// Graph weighted by distance with edges in both directions along all `ways`.
+    pub(crate) fn two_way_graph(
+        nodes: &HashMap<NodeId, NodeInfo>,
+        ways: &HashMap<WayId, WayInfo>,
+    ) -> Graph {
+        let mut edges = Vec::new();
+        for way in ways.values() {
+            for pair in way.nodes.windows(2) {
+                edges.push((pair[0], pair[1], 50.0));
+                edges.push((pair[1], pair[0], 50.0));
+            }
+        }
+        Graph::new(nodes, &edges, Weighting::Distance)
+    }
+
+    /// Graph of a `size` by `size` grid of nodes 0.001 degrees apart and numbered from 1 row
+    /// by row, followed by a node without edges. Every other row is a one way street, their
+    /// directions alternating, and the speeds vary so that no two weightings agree.
+    pub(crate) fn grid_graph(size: i64, weighting: Weighting) -> Graph {
+        let id = |row: i64, column: i64| NodeId(row * size + column + 1);
+        let mut coordinates = Vec::new();
+        let mut edges = Vec::new();
+        for row in 0..size {
+            for column in 0..size {
+                coordinates.push((row as f64 * 0.001, column as f64 * 0.001));
+                let speed = [30.0, 50.0, 90.0][((row * 7 + column * 3) % 3) as usize];
+                if column + 1 < size {
+                    let (a, b) = (id(row, column), id(row, column + 1));
+                    match row % 4 {
+                        1 => edges.push((a, b, speed)),
+                        3 => edges.push((b, a, speed)),
+                        _ => edges.extend([(a, b, speed), (b, a, speed)]),
+                    }
+                }
+                if row + 1 < size {
+                    let (a, b) = (id(row, column), id(row + 1, column));
+                    edges.extend([(a, b, speed), (b, a, speed)]);
+                }
+            }
+        }
+        coordinates.push((-0.001, -0.001));
+        Graph::new(&nodes(&coordinates), &edges, weighting)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use osmpbfreader::NodeId;
+
+    use super::fixture;
+    use super::Map;
+    use super::Oneway;
+    use crate::graph::NodeIndex;
+    use crate::graph::Weighting;
+    use crate::profile::Profile;
+
+    fn map(ways: &[(i64, &[i64])]) -> Map {
+        let coordinates: Vec<(f64, f64)> = (0..8).map(|i| (0.0, i as f64 * 0.001)).collect();
+        let nodes = fixture::nodes(&coordinates);
+        Map::new(
+            nodes,
+            fixture::ways(ways),
+            &[],
+            Profile::Car,
+            Weighting::Distance,
+        )
+    }
+
+    /// Ids and nodes of the ways of `map`.
+    fn way_nodes(map: &Map) -> HashMap<i64, Vec<i64>> {
+        map.ways()
+            .iter()
+            .map(|(id, way)| (id.0, way.nodes.iter().map(|node| node.0).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn retain_nodes_cuts_ways_into_runs() {
+        let ways: [(i64, &[i64]); 4] = [
+            (10, &[1, 2, 3, 4, 5, 6, 7]),
+            (11, &[8, 3, 4]),
+            (12, &[4, 5, 6, 7, 8, 3, 1, 2]),
+            (-5, &[7, 8]),
+        ];
+        let mut pruned = Vec::new();
+        for _ in 0..2 {
+            let mut map = map(&ways);
+            let graph = map.graph();
+            let keep: Vec<bool> = (0..graph.node_count() as NodeIndex)
+                .map(|node| ![NodeId(3), NodeId(6)].contains(&graph.id(node)))
+                .collect();
+            assert_eq!(map.retain_nodes(&keep), 2);
+            assert!(map.ways().values().all(|way| way
+                .nodes
+                .iter()
+                .all(|node| map.graph().index(*node).is_some())));
+            pruned.push(way_nodes(&map));
+        }
+        // Runs of a single node are dropped, and new ids continue below the smallest one in
+        // the order of the ids of the cut ways.
+        let expected = HashMap::from([
+            (10, vec![1, 2]),
+            (-6, vec![4, 5]),
+            (12, vec![4, 5]),
+            (-7, vec![7, 8]),
+            (-8, vec![1, 2]),
+            (-5, vec![7, 8]),
+        ]);
+        assert_eq!(pruned[0], expected);
+        assert_eq!(pruned[1], expected);
+    }
+
+    #[test]
+    fn oneway_tags() {
+        let cases: [(&[&str], Oneway); 16] = [
+            (&["highway=residential"], Oneway::No),
+            (&["oneway=yes"], Oneway::Forward),
+            (&["oneway=true"], Oneway::Forward),
+            (&["oneway=1"], Oneway::Forward),
+            (&["oneway=-1"], Oneway::Backward),
+            (&["oneway=reverse"], Oneway::Backward),
+            (&["oneway=reversible"], Oneway::Closed),
+            (&["oneway=alternating"], Oneway::Closed),
+            (&["oneway=no"], Oneway::No),
+            (&["oneway=false", "highway=motorway"], Oneway::No),
+            (&["highway=motorway"], Oneway::Forward),
+            (&["highway=motorway_link"], Oneway::No),
+            (&["junction=roundabout"], Oneway::Forward),
+            (&["junction=circular"], Oneway::Forward),
+            (&["junction=roundabout", "oneway=-1"], Oneway::Backward),
+            (&["junction=roundabout", "oneway=0"], Oneway::No),
+        ];
+        for (tags, expected) in cases {
+            assert_eq!(fixture::tagged_way(tags).oneway(), expected, "{:?}", tags);
+        }
+    }
+}
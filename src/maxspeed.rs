@@ -0,0 +1,175 @@
+use osmpbfreader::Tags;
+
+const MPH: f64 = 1.609344;
+const KNOTS: f64 = 1.852;
+
+/// Parses a `maxspeed` value into km/h. Handles plain numbers, `mph` and `knots` units,
+/// `walk`, implicit country values like `DE:urban` and lists separated by `;`, of which the
+/// first value is used. Returns `None` for values that do not imply a limit, e.g. `none`,
+/// `signals` or `variable`.
+pub fn parse_maxspeed(value: &str) -> Option<f64> {
+    let value = value.split(';').next()?.trim();
+    if let Some((country, kind)) = value.split_once(':') {
+        return implicit_maxspeed(country, kind);
+    }
+    match value {
+        "walk" => return Some(5.0),
+        "none" | "signals" | "variable" => return None,
+        _ => {}
+    }
+
+    let (number, factor) = if let Some(number) = value.strip_suffix("mph") {
+        (number, MPH)
+    } else if let Some(number) = value.strip_suffix("knots") {
+        (number, KNOTS)
+    } else if let Some(number) = value.strip_suffix("km/h") {
+        (number, 1.0)
+    } else {
+        (value, 1.0)
+    };
+    let speed = number.trim().parse::<f64>().ok()? * factor;
+    if speed > 0.0 {
+        Some(speed)
+    } else {
+        None
+    }
+}
+
+/// Speed limit in km/h implied by the national rules of `country` for roads of `kind`.
+fn implicit_maxspeed(country: &str, kind: &str) -> Option<f64> {
+    let speed = match (country, kind) {
+        (_, "walk") => 5.0,
+        (_, "living_street") => 20.0,
+        (_, "zone30") | (_, "zone:30") => 30.0,
+        (_, "zone20") | (_, "zone:20") => 20.0,
+        (_, "urban") => 50.0,
+        ("GB", "nsl_single") => 60.0 * MPH,
+        ("GB", "nsl_dual") | ("GB", "motorway") => 70.0 * MPH,
+        ("AT", "rural") | ("DE", "rural") => 100.0,
+        ("FR", "rural") => 80.0,
+        (_, "rural") => 90.0,
+        ("CZ", "trunk") | ("HU", "trunk") => 110.0,
+        ("PL", "trunk") | ("PL", "expressway") => 120.0,
+        (_, "trunk") => 100.0,
+        ("DE", "motorway") => return None,
+        ("PL", "motorway") => 140.0,
+        (_, "motorway") => 130.0,
+        _ => return None,
+    };
+    Some(speed)
+}
+
+/// Speed limit in km/h for travelling along the way with `tags`, or against it if `forward`
+/// is not set. Direction specific tags take precedence over `maxspeed`, and `maxspeed:type`
+/// is used for ways with only an implicit limit. An explicit `none` is final and is not
+/// overridden by the less specific tags after it.
+pub fn maxspeed(tags: &Tags, forward: bool) -> Option<f64> {
+    let directional = if forward {
+        "maxspeed:forward"
+    } else {
+        "maxspeed:backward"
+    };
+    for value in [directional, "maxspeed", "maxspeed:type", "source:maxspeed"]
+        .iter()
+        .filter_map(|key| tags.get(*key))
+    {
+        if value.split(';').next().map(str::trim) == Some("none") {
+            return None;
+        }
+        if let Some(speed) = parse_maxspeed(value) {
+            return Some(speed);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use osmpbfreader::Tags;
+
+    use super::maxspeed;
+    use super::parse_maxspeed;
+    use super::KNOTS;
+    use super::MPH;
+
+    fn assert_speed(value: &str, expected: Option<f64>) {
+        match (parse_maxspeed(value), expected) {
+            (Some(speed), Some(expected)) => {
+                assert!((speed - expected).abs() < 1e-9, "{}: {}", value, speed)
+            }
+            (speed, expected) => assert_eq!(speed, expected, "{}", value),
+        }
+    }
+
+    #[test]
+    fn numbers_and_units() {
+        assert_speed("50", Some(50.0));
+        assert_speed(" 80 ", Some(80.0));
+        assert_speed("50 km/h", Some(50.0));
+        assert_speed("30 mph", Some(30.0 * MPH));
+        assert_speed("30mph", Some(30.0 * MPH));
+        assert_speed("5 knots", Some(5.0 * KNOTS));
+        assert_speed("0", None);
+        assert_speed("fast", None);
+        assert_speed("", None);
+    }
+
+    #[test]
+    fn lists_use_the_first_value() {
+        assert_speed("50;30", Some(50.0));
+        assert_speed("30 mph; 20 mph", Some(30.0 * MPH));
+        assert_speed("none;50", None);
+    }
+
+    #[test]
+    fn values_without_a_limit() {
+        assert_speed("walk", Some(5.0));
+        assert_speed("none", None);
+        assert_speed("signals", None);
+        assert_speed("variable", None);
+    }
+
+    #[test]
+    fn implicit_country_values() {
+        assert_speed("SK:urban", Some(50.0));
+        assert_speed("SK:rural", Some(90.0));
+        assert_speed("SK:motorway", Some(130.0));
+        assert_speed("DE:rural", Some(100.0));
+        assert_speed("DE:motorway", None);
+        assert_speed("DE:zone:30", Some(30.0));
+        assert_speed("DE:zone30", Some(30.0));
+        assert_speed("DE:living_street", Some(20.0));
+        assert_speed("FR:rural", Some(80.0));
+        assert_speed("GB:nsl_single", Some(60.0 * MPH));
+        assert_speed("GB:nsl_dual", Some(70.0 * MPH));
+        assert_speed("PL:expressway", Some(120.0));
+        assert_speed("CZ:trunk", Some(110.0));
+        assert_speed("XX:unknown", None);
+    }
+
+    #[test]
+    fn tag_precedence() {
+        let mut tags = Tags::new();
+        tags.insert("maxspeed:type".into(), "SK:urban".into());
+        assert_eq!(maxspeed(&tags, true), Some(50.0));
+        tags.insert("maxspeed".into(), "70".into());
+        assert_eq!(maxspeed(&tags, true), Some(70.0));
+        tags.insert("maxspeed:forward".into(), "90".into());
+        tags.insert("maxspeed:backward".into(), "60".into());
+        assert_eq!(maxspeed(&tags, true), Some(90.0));
+        assert_eq!(maxspeed(&tags, false), Some(60.0));
+
+        let mut tags = Tags::new();
+        tags.insert("maxspeed".into(), "none".into());
+        tags.insert("source:maxspeed".into(), "DE:rural".into());
+        assert_eq!(maxspeed(&tags, true), None);
+        tags.insert("maxspeed:forward".into(), "120".into());
+        assert_eq!(maxspeed(&tags, true), Some(120.0));
+        assert_eq!(maxspeed(&tags, false), None);
+
+        let mut tags = Tags::new();
+        tags.insert("maxspeed".into(), "signals".into());
+        tags.insert("source:maxspeed".into(), "DE:rural".into());
+        assert_eq!(maxspeed(&tags, true), Some(100.0));
+    }
+}
@@ -0,0 +1,318 @@
+use std::fmt;
+use std::str::FromStr;
+
+use osmpbfreader::Tags;
+
+use crate::maxspeed::maxspeed;
+use crate::Oneway;
+use crate::WayInfo;
+
+/// Mode of transport deciding which ways are usable and at what speed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Car,
+    Bicycle,
+    Foot,
+}
+
+/// Values of access tags that forbid the way for the mode of transport.
+const DENIED: [&str; 5] = ["no", "private", "agricultural", "forestry", "use_sidepath"];
+
+impl Profile {
+    /// Access keys from the most general to the most specific one. The most specific key
+    /// present on a way decides.
+    fn access_keys(&self) -> &'static [&'static str] {
+        match self {
+            Profile::Car => &["access", "vehicle", "motor_vehicle", "motorcar"],
+            Profile::Bicycle => &["access", "vehicle", "bicycle"],
+            Profile::Foot => &["access", "foot"],
+        }
+    }
+
+    /// Keys of turn restriction relations from the most general to the most specific one.
+    pub fn restriction_keys(&self) -> &'static [&'static str] {
+        match self {
+            Profile::Car => &[
+                "restriction",
+                "restriction:motor_vehicle",
+                "restriction:motorcar",
+            ],
+            Profile::Bicycle => &["restriction", "restriction:bicycle"],
+            Profile::Foot => &["restriction:foot"],
+        }
+    }
+
+    /// Values of the `except` tag of turn restrictions that exempt this profile.
+    pub fn restriction_modes(&self) -> &'static [&'static str] {
+        match self {
+            Profile::Car => &["motorcar", "motor_vehicle"],
+            Profile::Bicycle => &["bicycle"],
+            Profile::Foot => &["foot"],
+        }
+    }
+
+    /// Speed in km/h on ways of the given `highway` class, `None` if the class is not
+    /// usable unless explicitly allowed by an access tag. Bicycles are slower on busy roads
+    /// than on cycleways, so cycleways are preferred when routing by travel time.
+    pub fn highway_speed(&self, highway: &str) -> Option<f64> {
+        let speed = match self {
+            Profile::Car => match highway {
+                "motorway" => 120.0,
+                "motorway_link" => 60.0,
+                "trunk" => 90.0,
+                "trunk_link" => 50.0,
+                "primary" => 80.0,
+                "primary_link" => 50.0,
+                "secondary" => 70.0,
+                "secondary_link" => 40.0,
+                "tertiary" => 60.0,
+                "tertiary_link" => 40.0,
+                "unclassified" => 50.0,
+                "residential" => 40.0,
+                "road" => 30.0,
+                "service" => 20.0,
+                "living_street" => 10.0,
+                _ => return None,
+            },
+            Profile::Bicycle => match highway {
+                "cycleway" => 20.0,
+                "primary" | "primary_link" => 12.0,
+                "secondary" | "secondary_link" => 14.0,
+                "tertiary" | "tertiary_link" => 16.0,
+                "unclassified" | "residential" | "road" => 16.0,
+                "service" | "track" => 12.0,
+                "living_street" => 10.0,
+                "path" => 10.0,
+                _ => return None,
+            },
+            Profile::Foot => match highway {
+                "footway" | "pedestrian" | "path" | "living_street" => 5.0,
+                "residential" | "service" | "track" | "unclassified" | "road" => 5.0,
+                "tertiary" | "tertiary_link" | "secondary" | "secondary_link" => 4.5,
+                "primary" | "primary_link" | "trunk" | "trunk_link" => 4.0,
+                "cycleway" => 4.5,
+                "steps" => 2.0,
+                _ => return None,
+            },
+        };
+        Some(speed)
+    }
+
+    /// Speed used on ways whose class is not usable by default, but which are explicitly
+    /// opened to this mode of transport, e.g. footways with `bicycle=yes`.
+    fn explicit_access_speed(&self) -> f64 {
+        match self {
+            Profile::Car => 15.0,
+            Profile::Bicycle => 10.0,
+            Profile::Foot => 5.0,
+        }
+    }
+
+    /// Speed in km/h on a way with `tags`, or `None` if the way is not usable with this
+    /// profile.
+    pub fn speed(&self, tags: &Tags) -> Option<f64> {
+        let highway = tags.get("highway")?;
+        if highway == "construction" || highway == "proposed" {
+            return None;
+        }
+        if *self != Profile::Car && tags.contains("motorroad", "yes") {
+            return None;
+        }
+        if *self != Profile::Foot && highway == "steps" {
+            return None;
+        }
+
+        let access = self
+            .access_keys()
+            .iter()
+            .filter_map(|key| tags.get(*key))
+            .next_back();
+        match access.map(|v| v.as_str()) {
+            Some(value) if DENIED.contains(&value) => None,
+            Some("yes") | Some("designated") | Some("permissive") => Some(
+                self.highway_speed(highway)
+                    .unwrap_or_else(|| self.explicit_access_speed()),
+            ),
+            _ => self.highway_speed(highway),
+        }
+    }
+
+    /// Speed in km/h when travelling along the way with `tags`, or against it if `forward`
+    /// is not set. Cars drive at the posted limit where there is one, while cyclists and
+    /// pedestrians only slow down to it.
+    pub fn travel_speed(&self, tags: &Tags, forward: bool) -> Option<f64> {
+        let speed = self.speed(tags)?;
+        let limit = maxspeed(tags, forward);
+        Some(match (self, limit) {
+            (Profile::Car, Some(limit)) => limit,
+            (_, Some(limit)) => speed.min(limit),
+            (_, None) => speed,
+        })
+    }
+
+    /// Whether a way with `tags` is usable with this profile.
+    pub fn accepts(&self, tags: &Tags) -> bool {
+        self.speed(tags).is_some()
+    }
+
+    /// Directions in which `way` can be traversed. Pedestrians may walk both ways on oneway
+    /// streets and cyclists may do so where `oneway:bicycle=no` or a contraflow lane exists.
+    pub fn oneway(&self, way: &WayInfo) -> Oneway {
+        let oneway = way.oneway();
+        match self {
+            Profile::Car => oneway,
+            Profile::Bicycle => {
+                let contraflow = way.tags.contains("oneway:bicycle", "no")
+                    || way
+                        .tags
+                        .get("cycleway")
+                        .is_some_and(|v| v.starts_with("opposite"));
+                if contraflow && oneway != Oneway::Closed {
+                    Oneway::No
+                } else {
+                    oneway
+                }
+            }
+            Profile::Foot => Oneway::No,
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "car" => Ok(Profile::Car),
+            "bicycle" | "bike" => Ok(Profile::Bicycle),
+            "foot" | "walk" => Ok(Profile::Foot),
+            _ => Err(format!("unknown profile {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Profile::Car => "car",
+            Profile::Bicycle => "bicycle",
+            Profile::Foot => "foot",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profile;
+    use crate::map::fixture;
+    use crate::Oneway;
+
+    #[test]
+    fn access_tags() {
+        let cases: [(Profile, &[&str], Option<f64>); 18] = [
+            (Profile::Car, &["highway=residential"], Some(40.0)),
+            (Profile::Car, &["highway=residential", "access=no"], None),
+            (
+                Profile::Car,
+                &["highway=residential", "access=no", "motorcar=yes"],
+                Some(40.0),
+            ),
+            (
+                Profile::Car,
+                &["highway=residential", "motor_vehicle=private"],
+                None,
+            ),
+            (
+                Profile::Car,
+                &[
+                    "highway=residential",
+                    "vehicle=no",
+                    "motor_vehicle=destination",
+                ],
+                Some(40.0),
+            ),
+            (Profile::Car, &["highway=footway"], None),
+            (
+                Profile::Car,
+                &["highway=footway", "motorcar=yes"],
+                Some(15.0),
+            ),
+            (Profile::Car, &["highway=construction", "access=yes"], None),
+            (Profile::Bicycle, &["highway=footway"], None),
+            (
+                Profile::Bicycle,
+                &["highway=footway", "bicycle=designated"],
+                Some(10.0),
+            ),
+            (
+                Profile::Bicycle,
+                &["highway=cycleway", "access=no", "bicycle=yes"],
+                Some(20.0),
+            ),
+            (
+                Profile::Bicycle,
+                &["highway=residential", "vehicle=no"],
+                None,
+            ),
+            (
+                Profile::Bicycle,
+                &["highway=primary", "motorroad=yes"],
+                None,
+            ),
+            (Profile::Bicycle, &["highway=steps"], None),
+            (Profile::Foot, &["highway=steps"], Some(2.0)),
+            (
+                Profile::Foot,
+                &["highway=residential", "access=no", "foot=yes"],
+                Some(5.0),
+            ),
+            (
+                Profile::Foot,
+                &["highway=primary", "foot=use_sidepath"],
+                None,
+            ),
+            (Profile::Foot, &["highway=motorway"], None),
+        ];
+        for (profile, tags, expected) in cases {
+            let way = fixture::tagged_way(tags);
+            assert_eq!(profile.speed(&way.tags), expected, "{} {:?}", profile, tags);
+        }
+    }
+
+    #[test]
+    fn oneway_per_profile() {
+        let cases: [(Profile, &[&str], Oneway); 10] = [
+            (Profile::Car, &["oneway=yes"], Oneway::Forward),
+            (Profile::Car, &["oneway=-1"], Oneway::Backward),
+            (Profile::Car, &["junction=roundabout"], Oneway::Forward),
+            (
+                Profile::Car,
+                &["oneway=yes", "oneway:bicycle=no"],
+                Oneway::Forward,
+            ),
+            (Profile::Bicycle, &["oneway=yes"], Oneway::Forward),
+            (
+                Profile::Bicycle,
+                &["oneway=yes", "oneway:bicycle=no"],
+                Oneway::No,
+            ),
+            (
+                Profile::Bicycle,
+                &["oneway=-1", "cycleway=opposite_lane"],
+                Oneway::No,
+            ),
+            (
+                Profile::Bicycle,
+                &["oneway=reversible", "oneway:bicycle=no"],
+                Oneway::Closed,
+            ),
+            (Profile::Foot, &["oneway=yes"], Oneway::No),
+            (Profile::Foot, &["highway=motorway"], Oneway::No),
+        ];
+        for (profile, tags, expected) in cases {
+            let way = fixture::tagged_way(tags);
+            assert_eq!(profile.oneway(&way), expected, "{} {:?}", profile, tags);
+        }
+    }
+}
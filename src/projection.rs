@@ -0,0 +1,319 @@
+use std::f64::consts::FRAC_PI_4;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::deg2rad;
+use crate::BoundingBox;
+use crate::EARTH_RADIUS;
+
+/// Radius of the sphere of the Web Mercator projection in meters.
+const WEB_MERCATOR_RADIUS: f64 = 6_378_137.0;
+
+/// Web Mercator is undefined at the poles, so latitudes are clamped to the square of the
+/// web map tiles.
+const WEB_MERCATOR_MAX_LAT: f64 = 85.051_128_78;
+
+/// Viewports do not zoom in further than this many projected meters per pixel.
+const MIN_SCALE: f64 = 0.01;
+
+/// Mapping of latitude and longitude to planar coordinates in meters, with `y` growing to
+/// the north.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Projection {
+    /// Spherical Mercator of web map tiles, conformal but stretched away from the equator.
+    #[default]
+    WebMercator,
+    /// Longitudes scaled by the cosine of `center_lat`, in degrees, which is nearly true to
+    /// scale in a region around that latitude.
+    Equirectangular { center_lat: f64 },
+}
+
+impl Projection {
+    /// The same projection, with equirectangular ones centered on `region`.
+    pub fn centered_on(self, region: &BoundingBox) -> Self {
+        match self {
+            Projection::WebMercator => Projection::WebMercator,
+            Projection::Equirectangular { .. } => Projection::Equirectangular {
+                center_lat: (region.min_lat + region.max_lat) / 2.0,
+            },
+        }
+    }
+
+    /// Planar coordinates of `lat`, `lon` in degrees.
+    pub fn project(&self, lat: f64, lon: f64) -> (f64, f64) {
+        match self {
+            Projection::WebMercator => {
+                let lat = lat.clamp(-WEB_MERCATOR_MAX_LAT, WEB_MERCATOR_MAX_LAT);
+                (
+                    WEB_MERCATOR_RADIUS * deg2rad(lon),
+                    WEB_MERCATOR_RADIUS * (FRAC_PI_4 + deg2rad(lat) / 2.0).tan().ln(),
+                )
+            }
+            Projection::Equirectangular { center_lat } => {
+                let radius = EARTH_RADIUS * 1000.0;
+                (
+                    radius * deg2rad(lon) * deg2rad(*center_lat).cos(),
+                    radius * deg2rad(lat),
+                )
+            }
+        }
+    }
+
+    /// Latitude and longitude in degrees of the planar coordinates `x`, `y`.
+    pub fn unproject(&self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            Projection::WebMercator => (
+                (2.0 * (y / WEB_MERCATOR_RADIUS).exp().atan() - 2.0 * FRAC_PI_4).to_degrees(),
+                (x / WEB_MERCATOR_RADIUS).to_degrees(),
+            ),
+            Projection::Equirectangular { center_lat } => {
+                let radius = EARTH_RADIUS * 1000.0;
+                (
+                    (y / radius).to_degrees(),
+                    (x / (radius * deg2rad(*center_lat).cos())).to_degrees(),
+                )
+            }
+        }
+    }
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    /// Parses `mercator` or `equirectangular`, the latter centered on the equator until
+    /// `centered_on` is used.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mercator" => Ok(Projection::WebMercator),
+            "equirectangular" => Ok(Projection::Equirectangular { center_lat: 0.0 }),
+            _ => Err(format!("unknown projection {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Projection::WebMercator => "mercator",
+            Projection::Equirectangular { .. } => "equirectangular",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Mapping of projected coordinates to the pixels of a `width` x `height` image, with the
+/// same scale along both axes and `y` growing downwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    /// Projected coordinates shown in the middle of the image.
+    center: (f64, f64),
+    /// Projected meters per pixel.
+    scale: f64,
+    width: u32,
+    height: u32,
+}
+
+impl Viewport {
+    /// Viewport of a `width` x `height` image showing all of `region` as large as possible
+    /// without distorting it.
+    pub fn fit(projection: &Projection, region: &BoundingBox, width: u32, height: u32) -> Self {
+        let min = projection.project(region.min_lat, region.min_lon);
+        let max = projection.project(region.max_lat, region.max_lon);
+        let scale = ((max.0 - min.0) / width as f64).max((max.1 - min.1) / height as f64);
+        Viewport {
+            center: ((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0),
+            scale: if scale > 0.0 { scale } else { 1.0 },
+            width,
+            height,
+        }
+    }
+
+    /// Projected meters per pixel.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Pixel of the projected `point`, possibly outside the image.
+    pub fn pixel(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            self.width as f64 / 2.0 + (x - self.center.0) / self.scale,
+            self.height as f64 / 2.0 - (y - self.center.1) / self.scale,
+        )
+    }
+
+    /// Projected point shown at the pixel `x`, `y`.
+    pub fn point(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.center.0 + (x - self.width as f64 / 2.0) * self.scale,
+            self.center.1 - (y - self.height as f64 / 2.0) * self.scale,
+        )
+    }
+
+    /// Projected corners of the image, the minimum and the maximum.
+    pub fn extent(&self) -> ((f64, f64), (f64, f64)) {
+        let (left, top) = self.point(0.0, 0.0);
+        let (right, bottom) = self.point(self.width as f64, self.height as f64);
+        ((left, bottom), (right, top))
+    }
+
+    /// Whether the projected box between `min` and `max` overlaps the image.
+    pub fn overlaps(&self, min: (f64, f64), max: (f64, f64)) -> bool {
+        let (extent_min, extent_max) = self.extent();
+        max.0 >= extent_min.0
+            && min.0 <= extent_max.0
+            && max.1 >= extent_min.1
+            && min.1 <= extent_max.1
+    }
+
+    /// Moves the content by `dx`, `dy` pixels.
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        self.center.0 -= dx * self.scale;
+        self.center.1 += dy * self.scale;
+    }
+
+    /// Magnifies the content by `factor`, keeping the pixel `x`, `y` in place.
+    pub fn zoom(&mut self, factor: f64, x: f64, y: f64) {
+        if self.scale / factor < MIN_SCALE {
+            return;
+        }
+        let point = self.point(x, y);
+        self.scale /= factor;
+        self.center = (
+            point.0 - (x - self.width as f64 / 2.0) * self.scale,
+            point.1 + (y - self.height as f64 / 2.0) * self.scale,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Projection;
+    use super::Viewport;
+    use super::MIN_SCALE;
+    use super::WEB_MERCATOR_MAX_LAT;
+    use crate::BoundingBox;
+
+    const PROJECTIONS: [Projection; 3] = [
+        Projection::WebMercator,
+        Projection::Equirectangular { center_lat: 0.0 },
+        Projection::Equirectangular { center_lat: 52.5 },
+    ];
+
+    fn assert_close(a: (f64, f64), b: (f64, f64), tolerance: f64) {
+        assert!(
+            (a.0 - b.0).abs() < tolerance && (a.1 - b.1).abs() < tolerance,
+            "{:?} {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn unproject_inverts_project() {
+        for projection in PROJECTIONS {
+            for lat in (-17..=17).map(|i| i as f64 * 5.0) {
+                for lon in (-36..=36).map(|i| i as f64 * 5.0) {
+                    let (x, y) = projection.project(lat, lon);
+                    assert_close(projection.unproject(x, y), (lat, lon), 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn web_mercator_is_clamped_near_the_poles() {
+        let projection = Projection::WebMercator;
+        assert_close(projection.project(0.0, 0.0), (0.0, 0.0), 1e-9);
+        let edge = projection.project(WEB_MERCATOR_MAX_LAT, 180.0);
+        assert_close(projection.project(90.0, 180.0), edge, 1e-6);
+        // The clamped latitude spans the square of the web map tiles.
+        assert!((edge.0 - edge.1).abs() < 1.0);
+        assert_close(projection.project(-90.0, 0.0), (0.0, -edge.1), 1e-6);
+    }
+
+    #[test]
+    fn fit_shows_the_whole_region() {
+        let region = BoundingBox {
+            min_lat: 52.4,
+            min_lon: 13.2,
+            max_lat: 52.6,
+            max_lon: 13.6,
+        };
+        for projection in PROJECTIONS {
+            for (width, height) in [(800, 600), (300, 900), (1, 1)] {
+                let viewport = Viewport::fit(&projection, &region, width, height);
+                let min = viewport.pixel(projection.project(region.min_lat, region.min_lon));
+                let max = viewport.pixel(projection.project(region.max_lat, region.max_lon));
+                let (width, height) = (width as f64, height as f64);
+                for (x, y) in [min, max] {
+                    assert!((-1e-6..=width + 1e-6).contains(&x), "{}", x);
+                    assert!((-1e-6..=height + 1e-6).contains(&y), "{}", y);
+                }
+                // The region fills one of the dimensions of the image.
+                let filled_width = ((max.0 - min.0) - width).abs();
+                let filled_height = ((min.1 - max.1) - height).abs();
+                assert!(filled_width.min(filled_height) < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn fit_of_a_single_point() {
+        let region = BoundingBox {
+            min_lat: 10.0,
+            min_lon: 20.0,
+            max_lat: 10.0,
+            max_lon: 20.0,
+        };
+        let projection = Projection::WebMercator;
+        let viewport = Viewport::fit(&projection, &region, 400, 200);
+        assert_eq!(viewport.scale(), 1.0);
+        assert_close(
+            viewport.pixel(projection.project(10.0, 20.0)),
+            (200.0, 100.0),
+            1e-6,
+        );
+    }
+
+    #[test]
+    fn zoom_keeps_the_pixel_in_place() {
+        let region = BoundingBox {
+            min_lat: 0.0,
+            min_lon: 0.0,
+            max_lat: 0.01,
+            max_lon: 0.01,
+        };
+        let mut viewport = Viewport::fit(&Projection::WebMercator, &region, 500, 500);
+        let point = viewport.point(120.0, 340.0);
+        let scale = viewport.scale();
+        viewport.zoom(2.0, 120.0, 340.0);
+        assert!((viewport.scale() - scale / 2.0).abs() < 1e-12);
+        assert_close(viewport.point(120.0, 340.0), point, 1e-6);
+        viewport.zoom(0.25, 120.0, 340.0);
+        assert!((viewport.scale() - scale * 2.0).abs() < 1e-12);
+        assert_close(viewport.point(120.0, 340.0), point, 1e-6);
+        assert_close(viewport.pixel(point), (120.0, 340.0), 1e-6);
+    }
+
+    #[test]
+    fn zoom_stops_at_the_minimum_scale() {
+        let region = BoundingBox {
+            min_lat: 0.0,
+            min_lon: 0.0,
+            max_lat: 0.01,
+            max_lon: 0.01,
+        };
+        let mut viewport = Viewport::fit(&Projection::WebMercator, &region, 500, 500);
+        for _ in 0..100 {
+            viewport.zoom(2.0, 10.0, 20.0);
+            assert!(viewport.scale() >= MIN_SCALE);
+        }
+        assert!(viewport.scale() < 2.0 * MIN_SCALE);
+        let before = viewport;
+        viewport.zoom(2.0, 10.0, 20.0);
+        assert_eq!(viewport, before);
+        // Zooming out is still possible.
+        viewport.zoom(0.5, 10.0, 20.0);
+        assert!(viewport.scale() > before.scale());
+    }
+}
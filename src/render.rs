@@ -0,0 +1,867 @@
+use sdl2::event::Event;
+use sdl2::image::SaveSurface;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Point;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::render::RenderTarget;
+use sdl2::surface::Surface;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::graph::Graph;
+use crate::graph::NodeIndex;
+use crate::graph::INVALID_NODE;
+use crate::projection::Projection;
+use crate::projection::Viewport;
+use crate::router::Algorithm;
+use crate::router::Router;
+use crate::routing::Path;
+use crate::routing::SearchObserver;
+use crate::BoundingBox;
+use crate::Map;
+use crate::WayInfo;
+
+const WIDTH: u32 = 1600;
+const HEIGHT: u32 = 800;
+const TITLE: &str = "rust-sdl2 demo";
+/// Side of the squares marking the origin and the destination, in pixels.
+const MARKER_SIZE: u32 = 9;
+/// Presses moving the mouse by more pixels than this pan the map instead of picking a node.
+const DRAG_THRESHOLD: i32 = 4;
+/// Pixels the arrow keys pan the map by.
+const PAN_STEP: f64 = 100.0;
+/// Scale change of one step of the wheel or the zoom keys.
+const ZOOM_STEP: f64 = 1.25;
+/// Colors of the routes of the different algorithms in pictures, and of the components.
+const PALETTE: [Color; 6] = [
+    Color::RGB(0, 0, 255),
+    Color::RGB(0, 160, 0),
+    Color::RGB(230, 120, 0),
+    Color::RGB(160, 0, 160),
+    Color::RGB(0, 160, 160),
+    Color::RGB(120, 80, 0),
+];
+/// Width of the lines of routes in pixels.
+const ROUTE_WIDTH: i32 = 4;
+/// Searches are animated over this many frames at the initial speed.
+const ANIMATION_FRAMES: usize = 300;
+/// Colors of the nodes settled by the forward and the backward search, and of the frontier.
+const FORWARD_SEARCH_COLOR: Color = Color::RGB(60, 110, 230);
+const BACKWARD_SEARCH_COLOR: Color = Color::RGB(190, 60, 190);
+const FRONTIER_COLOR: Color = Color::RGB(255, 150, 0);
+/// Side of the squares marking the nodes of animated searches, in pixels.
+const SEARCH_NODE_SIZE: u32 = 3;
+/// Color of the road network under overlays.
+const BACKGROUND_NETWORK: Color = Color::RGB(200, 200, 200);
+/// Color of the search tree overlay.
+const TREE_COLOR: Color = Color::RGB(255, 140, 0);
+
+/// Look of the ways of some `highway` classes.
+struct RoadStyle {
+    classes: &'static [&'static str],
+    color: Color,
+    /// Width of the lines in pixels.
+    width: i32,
+    /// The ways are hidden in the viewer when zoomed out to more projected meters per pixel
+    /// than this.
+    max_scale: f64,
+}
+
+/// Styles of the `highway` classes from the most to the least important. Less important
+/// ways are drawn under the others and hidden first when zooming out.
+const ROAD_STYLES: [RoadStyle; 9] = [
+    RoadStyle {
+        classes: &["motorway", "motorway_link"],
+        color: Color::RGB(225, 60, 60),
+        width: 3,
+        max_scale: f64::INFINITY,
+    },
+    RoadStyle {
+        classes: &["trunk", "trunk_link"],
+        color: Color::RGB(235, 120, 50),
+        width: 3,
+        max_scale: f64::INFINITY,
+    },
+    RoadStyle {
+        classes: &["primary", "primary_link"],
+        color: Color::RGB(240, 170, 50),
+        width: 2,
+        max_scale: 400.0,
+    },
+    RoadStyle {
+        classes: &["secondary", "secondary_link"],
+        color: Color::RGB(210, 190, 40),
+        width: 2,
+        max_scale: 150.0,
+    },
+    RoadStyle {
+        classes: &["tertiary", "tertiary_link"],
+        color: Color::RGB(150, 150, 60),
+        width: 1,
+        max_scale: 60.0,
+    },
+    RoadStyle {
+        classes: &["residential", "unclassified", "living_street", "road"],
+        color: Color::RGB(110, 110, 110),
+        width: 1,
+        max_scale: 20.0,
+    },
+    RoadStyle {
+        classes: &["service"],
+        color: Color::RGB(150, 150, 150),
+        width: 1,
+        max_scale: 8.0,
+    },
+    RoadStyle {
+        classes: &["track"],
+        color: Color::RGB(150, 110, 60),
+        width: 1,
+        max_scale: 8.0,
+    },
+    RoadStyle {
+        classes: &[
+            "footway",
+            "path",
+            "pedestrian",
+            "steps",
+            "cycleway",
+            "bridleway",
+        ],
+        color: Color::RGB(70, 150, 70),
+        width: 1,
+        max_scale: 5.0,
+    },
+];
+
+/// Style of the classes missing from `ROAD_STYLES`.
+const OTHER_ROAD_STYLE: RoadStyle = RoadStyle {
+    classes: &[],
+    color: Color::RGB(180, 180, 180),
+    width: 1,
+    max_scale: 5.0,
+};
+
+/// Position in `ROAD_STYLES` of the style of `way_info`, its length for other classes.
+fn road_style(way_info: &WayInfo) -> usize {
+    let highway = way_info.tags.get("highway").map(|highway| highway.as_str());
+    ROAD_STYLES
+        .iter()
+        .position(|style| highway.is_some_and(|highway| style.classes.contains(&highway)))
+        .unwrap_or(ROAD_STYLES.len())
+}
+
+/// Draws a polyline through `pixels` that is `width` pixels thick.
+fn draw_thick_lines<T: RenderTarget>(canvas: &mut Canvas<T>, pixels: &[Point], width: i32) {
+    if width <= 1 {
+        canvas.draw_lines(pixels).unwrap();
+        return;
+    }
+    let mut shifted: Vec<Point> = Vec::with_capacity(pixels.len());
+    for d in 0..width {
+        let d = d - width / 2;
+        for offset in [Point::new(d, 0), Point::new(0, d)] {
+            shifted.clear();
+            shifted.extend(pixels.iter().map(|pixel| *pixel + offset));
+            canvas.draw_lines(&shifted[..]).unwrap();
+        }
+    }
+}
+
+/// SDL point of a pixel position.
+fn sdl_point((x, y): (f64, f64)) -> Point {
+    Point::new(x as i32, y as i32)
+}
+
+/// Projected polylines of all ways, built once per drawing.
+struct Geometry {
+    /// Projection centered on the map.
+    projection: Projection,
+    /// Region covered by the map.
+    region: BoundingBox,
+    /// Points of way `w` are `points[first_point[w]..first_point[w + 1]]`, with the ways in
+    /// reverse order of importance so that major roads are drawn over minor ones.
+    first_point: Vec<usize>,
+    points: Vec<(f64, f64)>,
+    /// Projected corners of the bounding box of every way.
+    boxes: Vec<((f64, f64), (f64, f64))>,
+    /// Position of the style of every way in `ROAD_STYLES`, see `road_style`.
+    styles: Vec<usize>,
+}
+
+impl Geometry {
+    fn new(map: &Map, projection: Projection) -> Self {
+        let graph = map.graph();
+        let coordinates = |way_info: &WayInfo| {
+            way_info
+                .nodes
+                .iter()
+                .map(|id| graph.coordinates(graph.index(*id).unwrap()))
+                .collect::<Vec<(f64, f64)>>()
+        };
+        let region = BoundingBox::enclosing(map.ways().values().flat_map(coordinates)).unwrap_or(
+            BoundingBox {
+                min_lat: 0.0,
+                min_lon: 0.0,
+                max_lat: 0.0,
+                max_lon: 0.0,
+            },
+        );
+        let projection = projection.centered_on(&region);
+
+        let mut ways: Vec<(usize, &WayInfo)> = map
+            .ways()
+            .values()
+            .map(|way_info| (road_style(way_info), way_info))
+            .collect();
+        ways.sort_by_key(|(style, _)| std::cmp::Reverse(*style));
+
+        let mut first_point = vec![0];
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        let mut boxes = Vec::new();
+        let mut styles = Vec::new();
+        for (style, way_info) in ways {
+            styles.push(style);
+            let start = points.len();
+            points.extend(
+                coordinates(way_info)
+                    .into_iter()
+                    .map(|(lat, lon)| projection.project(lat, lon)),
+            );
+            first_point.push(points.len());
+            let (mut min, mut max) = (points[start], points[start]);
+            for (x, y) in points[start..].iter() {
+                min = (min.0.min(*x), min.1.min(*y));
+                max = (max.0.max(*x), max.1.max(*y));
+            }
+            boxes.push((min, max));
+        }
+
+        Geometry {
+            projection,
+            region,
+            first_point,
+            points,
+            boxes,
+            styles,
+        }
+    }
+
+    /// Pixel of `lat`, `lon` in `viewport`.
+    fn pixel(&self, viewport: &Viewport, (lat, lon): (f64, f64)) -> Point {
+        sdl_point(viewport.pixel(self.projection.project(lat, lon)))
+    }
+
+    /// Draws the edges between the pairs of `nodes` overlapping `viewport` as straight lines
+    /// in the current draw color.
+    fn draw_edges<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        viewport: &Viewport,
+        graph: &Graph,
+        nodes: impl Iterator<Item = (NodeIndex, NodeIndex)>,
+    ) {
+        for (tail, head) in nodes {
+            let (a, b) = (graph.coordinates(tail), graph.coordinates(head));
+            let (a, b) = (
+                self.projection.project(a.0, a.1),
+                self.projection.project(b.0, b.1),
+            );
+            if !viewport.overlaps((a.0.min(b.0), a.1.min(b.1)), (a.0.max(b.0), a.1.max(b.1))) {
+                continue;
+            }
+            let (a, b) = (sdl_point(viewport.pixel(a)), sdl_point(viewport.pixel(b)));
+            canvas.draw_line(a, b).unwrap();
+        }
+    }
+
+    /// Draws the ways overlapping `viewport` as polylines styled by their `highway` class,
+    /// in `color` instead of the colors of the styles if given. With `level_of_detail`, ways
+    /// of minor classes are left out when zoomed out, see `RoadStyle::max_scale`.
+    fn draw<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        viewport: &Viewport,
+        color: Option<Color>,
+        level_of_detail: bool,
+    ) {
+        let mut pixels: Vec<Point> = Vec::new();
+        for (way, (min, max)) in self.boxes.iter().enumerate() {
+            let style = ROAD_STYLES
+                .get(self.styles[way])
+                .unwrap_or(&OTHER_ROAD_STYLE);
+            let hidden = level_of_detail && viewport.scale() > style.max_scale;
+            if hidden || !viewport.overlaps(*min, *max) {
+                continue;
+            }
+            canvas.set_draw_color(color.unwrap_or(style.color));
+            let way_points = &self.points[self.first_point[way]..self.first_point[way + 1]];
+            pixels.clear();
+            pixels.extend(
+                way_points
+                    .iter()
+                    .map(|point| sdl_point(viewport.pixel(*point))),
+            );
+            draw_thick_lines(canvas, &pixels, style.width);
+        }
+    }
+}
+
+/// Draws `path` `ROUTE_WIDTH` pixels thick in `color`.
+fn draw_path<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    geometry: &Geometry,
+    viewport: &Viewport,
+    graph: &Graph,
+    path: &Path,
+    color: Color,
+) {
+    let pixels: Vec<Point> = path
+        .nodes
+        .iter()
+        .filter_map(|id| graph.index(*id))
+        .map(|node| geometry.pixel(viewport, graph.coordinates(node)))
+        .collect();
+    canvas.set_draw_color(color);
+    draw_thick_lines(canvas, &pixels, ROUTE_WIDTH);
+}
+
+/// Draws a square marker in `color` on `node`.
+fn draw_marker<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    geometry: &Geometry,
+    viewport: &Viewport,
+    graph: &Graph,
+    node: NodeIndex,
+    color: Color,
+) {
+    canvas.set_draw_color(color);
+    let center = geometry.pixel(viewport, graph.coordinates(node));
+    let marker = Rect::from_center(center, MARKER_SIZE, MARKER_SIZE);
+    canvas.fill_rect(marker).unwrap();
+}
+
+/// Step of a search recorded for the animation.
+#[derive(Debug, Clone, Copy)]
+enum SearchStep {
+    /// `node` was settled, reached from `parent` unless it is a root.
+    Settled {
+        node: NodeIndex,
+        parent: Option<NodeIndex>,
+        forward: bool,
+    },
+    /// `node` joined the frontier.
+    Reached { node: NodeIndex },
+}
+
+/// Observer recording the steps of a search.
+#[derive(Debug, Default)]
+struct SearchRecording {
+    steps: Vec<SearchStep>,
+    /// Last node each node was reached from, in the forward and the backward search.
+    parents: [HashMap<NodeIndex, NodeIndex>; 2],
+}
+
+impl SearchObserver for SearchRecording {
+    fn settled(&mut self, node: NodeIndex, forward: bool) {
+        let parent = self.parents[forward as usize].get(&node).copied();
+        self.steps.push(SearchStep::Settled {
+            node,
+            parent,
+            forward,
+        });
+    }
+
+    fn relaxed(&mut self, tail: NodeIndex, head: NodeIndex, forward: bool) {
+        self.parents[forward as usize].insert(head, tail);
+        self.steps.push(SearchStep::Reached { node: head });
+    }
+}
+
+/// Replay of a recorded search between the origin and the destination picked in the
+/// window, followed by the route it found.
+struct Animation {
+    algorithm: Algorithm,
+    path: Option<Path>,
+    steps: Vec<SearchStep>,
+    /// Steps shown so far.
+    shown: usize,
+    /// Steps drawn onto the network texture, which is less than `shown` after a step or
+    /// when the texture was redrawn.
+    drawn: usize,
+    /// Nodes settled in the steps shown so far.
+    settled: usize,
+    /// Steps shown per frame when playing.
+    speed: usize,
+    playing: bool,
+}
+
+impl Animation {
+    /// Runs `algorithm` from `from` to `to` and starts replaying it.
+    fn new(router: &Router, algorithm: Algorithm, from: NodeIndex, to: NodeIndex) -> Self {
+        let graph = router.map().graph();
+        let mut recording = SearchRecording::default();
+        let path = router.route_observed(algorithm, graph.id(from), graph.id(to), &mut recording);
+        Animation {
+            algorithm,
+            path,
+            speed: (recording.steps.len() / ANIMATION_FRAMES).max(1),
+            steps: recording.steps,
+            shown: 0,
+            drawn: 0,
+            settled: 0,
+            playing: true,
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.shown == self.steps.len()
+    }
+
+    /// Shows `count` more steps.
+    fn advance(&mut self, count: usize) {
+        let end = (self.shown + count).min(self.steps.len());
+        for step in &self.steps[self.shown..end] {
+            if let SearchStep::Settled { .. } = step {
+                self.settled += 1;
+            }
+        }
+        self.shown = end;
+    }
+
+    /// Shows the steps up to the next settled node.
+    fn step(&mut self) {
+        while let Some(step) = self.steps.get(self.shown).copied() {
+            self.advance(1);
+            if let SearchStep::Settled { .. } = step {
+                break;
+            }
+        }
+    }
+
+    /// Draws the shown steps that are not drawn yet.
+    fn draw<T: RenderTarget>(
+        &mut self,
+        canvas: &mut Canvas<T>,
+        geometry: &Geometry,
+        viewport: &Viewport,
+        graph: &Graph,
+    ) {
+        for step in &self.steps[self.drawn..self.shown] {
+            let (node, color) = match *step {
+                SearchStep::Settled {
+                    node,
+                    parent,
+                    forward,
+                } => {
+                    let color = if forward {
+                        FORWARD_SEARCH_COLOR
+                    } else {
+                        BACKWARD_SEARCH_COLOR
+                    };
+                    if let Some(parent) = parent {
+                        canvas.set_draw_color(color);
+                        let edge = [(parent, node)].into_iter();
+                        geometry.draw_edges(canvas, viewport, graph, edge);
+                    }
+                    (node, color)
+                }
+                SearchStep::Reached { node } => (node, FRONTIER_COLOR),
+            };
+            canvas.set_draw_color(color);
+            let center = geometry.pixel(viewport, graph.coordinates(node));
+            let square = Rect::from_center(center, SEARCH_NODE_SIZE, SEARCH_NODE_SIZE);
+            canvas.fill_rect(square).unwrap();
+        }
+        self.drawn = self.shown;
+    }
+
+    /// Title of the window, the progress of the search or the route once it is shown.
+    fn title(&self) -> String {
+        if !self.finished() {
+            return format!(
+                "{}: {} nodes settled, {} steps per frame{}",
+                self.algorithm,
+                self.settled,
+                self.speed,
+                if self.playing { "" } else { ", paused" }
+            );
+        }
+        match &self.path {
+            Some(path) => format!(
+                "{}: {:.2} km, {:.1} min, {} nodes settled",
+                self.algorithm,
+                path.length / 1000.0,
+                path.duration / 60.0,
+                path.settled
+            ),
+            None => format!("{}: no route", self.algorithm),
+        }
+    }
+}
+
+/// What pictures show on top of the road network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overlay {
+    #[default]
+    None,
+    /// Nodes settled by Dijkstra from the origin of the first route until it reached the
+    /// destination, each connected to its parent.
+    Tree,
+    /// Weakly connected components, each in its own color.
+    Components,
+}
+
+impl FromStr for Overlay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Overlay::None),
+            "tree" => Ok(Overlay::Tree),
+            "components" => Ok(Overlay::Components),
+            _ => Err(format!("unknown overlay {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Overlay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Overlay::None => "none",
+            Overlay::Tree => "tree",
+            Overlay::Components => "components",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Size, region and overlay of a picture saved without a window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Picture {
+    pub width: u32,
+    pub height: u32,
+    /// Region to show, the whole map if `None`.
+    pub region: Option<BoundingBox>,
+    pub overlay: Overlay,
+}
+
+impl Default for Picture {
+    fn default() -> Self {
+        Picture {
+            width: WIDTH,
+            height: HEIGHT,
+            region: None,
+            overlay: Overlay::None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MapDrawing {
+    projection: Projection,
+}
+
+impl MapDrawing {
+    /// Drawing of maps in `projection`.
+    pub fn new(projection: Projection) -> Self {
+        Self { projection }
+    }
+
+    /// Draws the road network of the map of `router`, styled by `highway` class with minor
+    /// roads hidden when zoomed out. Left clicks pick the origin and the destination,
+    /// snapped to the nearest nodes. The search of the first of `algorithms` between them
+    /// is animated, with the settled nodes and the frontier, and the route it found is then
+    /// drawn over the network, with its length and duration in the title.
+    ///
+    /// Space pauses and resumes the animation, `]` and `[` double and halve its speed, `N`
+    /// shows the next settled node, Enter the whole search and Tab runs the search again
+    /// with the next of `algorithms`.
+    ///
+    /// Dragging with the left button or the arrow keys pan the map, the wheel zooms around
+    /// the cursor, `+` and `-` around the center of the window and `R` resets the view.
+    pub fn draw(&self, router: &Router, algorithms: &[Algorithm]) {
+        let map = router.map();
+        let graph = map.graph();
+        let sdl_context = sdl2::init().unwrap();
+        let video_subsystem = sdl_context.video().unwrap();
+        let window = video_subsystem
+            .window(TITLE, WIDTH, HEIGHT)
+            .position_centered()
+            .build()
+            .unwrap();
+        let mut canvas = window.into_canvas().build().unwrap();
+        let mut event_pump = sdl_context.event_pump().unwrap();
+        let mut origin: Option<NodeIndex> = None;
+        let mut destination: Option<NodeIndex> = None;
+        let mut algorithm = 0;
+        let mut animation: Option<Animation> = None;
+        let mut title = String::new();
+        let mut cursor = Point::new(WIDTH as i32 / 2, HEIGHT as i32 / 2);
+        // Position of the last left button press and whether the mouse was dragged since.
+        let mut press: Option<(Point, bool)> = None;
+
+        let geometry = Geometry::new(map, self.projection);
+        let fitted = Viewport::fit(&geometry.projection, &geometry.region, WIDTH, HEIGHT);
+        let mut view = fitted;
+        // The network is drawn into a texture, redrawn only when the view changes.
+        let texture_creator = canvas.texture_creator();
+        let mut network = texture_creator
+            .create_texture_target(None, WIDTH, HEIGHT)
+            .unwrap();
+        let mut drawn_view: Option<Viewport> = None;
+
+        'running: loop {
+            if let Some(animation) = animation.as_mut() {
+                if animation.playing {
+                    animation.advance(animation.speed);
+                }
+            }
+            // New steps of the animation are drawn onto the network, which is only redrawn
+            // with all the shown steps when the view changes.
+            let redraw = drawn_view != Some(view);
+            if redraw {
+                if let Some(animation) = animation.as_mut() {
+                    animation.drawn = 0;
+                }
+            }
+            let new_steps = animation
+                .as_ref()
+                .is_some_and(|animation| animation.drawn < animation.shown);
+            if redraw || new_steps {
+                canvas
+                    .with_texture_canvas(&mut network, |texture_canvas| {
+                        if redraw {
+                            texture_canvas.set_draw_color(Color::RGB(255, 255, 255));
+                            texture_canvas.clear();
+                            geometry.draw(texture_canvas, &view, None, true);
+                        }
+                        if let Some(animation) = animation.as_mut() {
+                            animation.draw(texture_canvas, &geometry, &view, graph);
+                        }
+                    })
+                    .unwrap();
+                drawn_view = Some(view);
+            }
+            canvas.copy(&network, None, None).unwrap();
+
+            if let Some(animation) = &animation {
+                if let (true, Some(path)) = (animation.finished(), &animation.path) {
+                    let color = Color::RGB(0, 0, 255);
+                    draw_path(&mut canvas, &geometry, &view, graph, path, color);
+                }
+            }
+            for (node, color) in [
+                (origin, Color::RGB(0, 160, 0)),
+                (destination, Color::RGB(0, 0, 0)),
+            ] {
+                if let Some(node) = node {
+                    draw_marker(&mut canvas, &geometry, &view, graph, node, color);
+                }
+            }
+
+            for event in event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. }
+                    | Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => break 'running,
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        let center = (WIDTH as f64 / 2.0, HEIGHT as f64 / 2.0);
+                        match keycode {
+                            Keycode::Left => view.pan(PAN_STEP, 0.0),
+                            Keycode::Right => view.pan(-PAN_STEP, 0.0),
+                            Keycode::Up => view.pan(0.0, PAN_STEP),
+                            Keycode::Down => view.pan(0.0, -PAN_STEP),
+                            Keycode::Plus | Keycode::Equals | Keycode::KpPlus => {
+                                view.zoom(ZOOM_STEP, center.0, center.1)
+                            }
+                            Keycode::Minus | Keycode::KpMinus => {
+                                view.zoom(1.0 / ZOOM_STEP, center.0, center.1)
+                            }
+                            Keycode::R | Keycode::Home => view = fitted,
+                            Keycode::Tab => {
+                                algorithm = (algorithm + 1) % algorithms.len();
+                                if let (Some(from), Some(to)) = (origin, destination) {
+                                    let next = algorithms[algorithm];
+                                    animation = Some(Animation::new(router, next, from, to));
+                                    // Clears the search shown before.
+                                    drawn_view = None;
+                                }
+                            }
+                            _ => {}
+                        }
+                        if let Some(animation) = animation.as_mut() {
+                            match keycode {
+                                Keycode::Space => animation.playing = !animation.playing,
+                                Keycode::RightBracket => animation.speed *= 2,
+                                Keycode::LeftBracket => {
+                                    animation.speed = (animation.speed / 2).max(1)
+                                }
+                                Keycode::N => {
+                                    animation.playing = false;
+                                    animation.step();
+                                }
+                                Keycode::Return | Keycode::KpEnter => {
+                                    animation.advance(animation.steps.len())
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    Event::MouseWheel { y, .. } => {
+                        view.zoom(ZOOM_STEP.powi(y), cursor.x() as f64, cursor.y() as f64);
+                    }
+                    Event::MouseMotion {
+                        mousestate,
+                        x,
+                        y,
+                        xrel,
+                        yrel,
+                        ..
+                    } => {
+                        cursor = Point::new(x, y);
+                        if let Some((start, dragged)) = press.as_mut() {
+                            if mousestate.left() {
+                                let moved = (x - start.x()).abs() + (y - start.y()).abs();
+                                *dragged |= moved > DRAG_THRESHOLD;
+                                view.pan(xrel as f64, yrel as f64);
+                            }
+                        }
+                    }
+                    Event::MouseButtonDown {
+                        mouse_btn: MouseButton::Left,
+                        x,
+                        y,
+                        ..
+                    } => press = Some((Point::new(x, y), false)),
+                    Event::MouseButtonUp {
+                        mouse_btn: MouseButton::Left,
+                        x,
+                        y,
+                        ..
+                    } => {
+                        if press.take().is_none_or(|(_, dragged)| dragged) {
+                            continue;
+                        }
+                        let (px, py) = view.point(x as f64, y as f64);
+                        let (lat, lon) = geometry.projection.unproject(px, py);
+                        let Some(node) = router.index().nearest_node(lat, lon) else {
+                            continue;
+                        };
+                        // A click after a complete route starts a new one.
+                        if origin.is_none() || destination.is_some() {
+                            origin = Some(node);
+                            destination = None;
+                            if animation.take().is_some() {
+                                drawn_view = None;
+                            }
+                            continue;
+                        }
+                        destination = Some(node);
+                        let from = origin.unwrap();
+                        animation = Some(Animation::new(router, algorithms[algorithm], from, node));
+                    }
+                    _ => {}
+                }
+            }
+
+            let new_title = match &animation {
+                Some(animation) => animation.title(),
+                None => format!("{}: {}", TITLE, algorithms[algorithm]),
+            };
+            if new_title != title {
+                canvas.window_mut().set_title(&new_title).unwrap();
+                title = new_title;
+            }
+
+            canvas.present();
+            ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        }
+    }
+
+    /// Saves a PNG of the road network of `map` with `routes` drawn over it, without opening
+    /// a window, so it also works on machines without a display. All ways are drawn whatever
+    /// the scale. The routes get the colors of `PALETTE` in order and their endpoints are
+    /// marked.
+    pub fn save(
+        &self,
+        map: &Map,
+        picture: &Picture,
+        routes: &[Path],
+        file: &std::path::Path,
+    ) -> Result<(), String> {
+        let graph = map.graph();
+        let geometry = Geometry::new(map, self.projection);
+        let region = picture.region.unwrap_or(geometry.region);
+        let view = Viewport::fit(&geometry.projection, &region, picture.width, picture.height);
+        let surface = Surface::new(picture.width, picture.height, PixelFormatEnum::RGB24)?;
+        let mut canvas = surface.into_canvas()?;
+
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.clear();
+        let network_color = match picture.overlay {
+            Overlay::None => None,
+            Overlay::Tree | Overlay::Components => Some(BACKGROUND_NETWORK),
+        };
+        geometry.draw(&mut canvas, &view, network_color, false);
+        match picture.overlay {
+            Overlay::None => {}
+            Overlay::Tree => {
+                let endpoints = routes.first().and_then(|path| {
+                    let from = graph.index(*path.nodes.first()?)?;
+                    let to = graph.index(*path.nodes.last()?)?;
+                    Some((from, to))
+                });
+                if let Some((from, to)) = endpoints {
+                    let (dist, parents) = graph.shortest_path_tree(from, false);
+                    let reach = dist[to as usize];
+                    let settled = (0..graph.node_count() as NodeIndex).filter(|node| {
+                        dist[*node as usize] <= reach && parents[*node as usize] != INVALID_NODE
+                    });
+                    canvas.set_draw_color(TREE_COLOR);
+                    let edges = settled.map(|node| (parents[node as usize], node));
+                    geometry.draw_edges(&mut canvas, &view, graph, edges);
+                }
+            }
+            Overlay::Components => {
+                let components = graph.components();
+                for tail in 0..graph.node_count() as NodeIndex {
+                    let Some(component) = components.id(tail) else {
+                        continue;
+                    };
+                    canvas.set_draw_color(PALETTE[component as usize % PALETTE.len()]);
+                    let edges = graph.out_edges(tail).map(|edge| (tail, edge.node));
+                    geometry.draw_edges(&mut canvas, &view, graph, edges);
+                }
+            }
+        }
+        for (i, path) in routes.iter().enumerate() {
+            let color = PALETTE[i % PALETTE.len()];
+            draw_path(&mut canvas, &geometry, &view, graph, path, color);
+        }
+        for path in routes.iter() {
+            let ends = [path.nodes.first(), path.nodes.last()];
+            for (id, color) in ends
+                .into_iter()
+                .zip([Color::RGB(0, 160, 0), Color::RGB(0, 0, 0)])
+            {
+                if let Some(node) = id.and_then(|id| graph.index(*id)) {
+                    draw_marker(&mut canvas, &geometry, &view, graph, node, color);
+                }
+            }
+        }
+
+        canvas.into_surface().save(file)
+    }
+}
@@ -0,0 +1,521 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io;
+use std::io::Write;
+
+use osmpbfreader::NodeId;
+use osmpbfreader::OsmId;
+use osmpbfreader::Relation;
+use osmpbfreader::WayId;
+
+use crate::graph::EdgeIndex;
+use crate::graph::Graph;
+use crate::profile::Profile;
+use crate::storage::corrupt;
+use crate::storage::Decoder;
+use crate::storage::Encode;
+use crate::storage::Encoder;
+use crate::storage::StorageError;
+use crate::WayInfo;
+
+/// Whether a restriction forbids the turn it describes or forbids all other turns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestrictionKind {
+    /// `no_left_turn`, `no_u_turn`, `no_straight_on`, ...
+    No,
+    /// `only_right_turn`, `only_straight_on`, ...
+    Only,
+}
+
+/// Element connecting the `from` and `to` ways of a restriction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Via {
+    Node(NodeId),
+    /// Ways in the order they are traversed.
+    Ways(Vec<WayId>),
+}
+
+/// Turn restriction read from a `type=restriction` relation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TurnRestriction {
+    pub kind: RestrictionKind,
+    pub from: WayId,
+    pub via: Via,
+    pub to: WayId,
+}
+
+impl TurnRestriction {
+    /// Reads the restriction from `relation` if it is a valid turn restriction that applies
+    /// to `profile`, considering mode specific `restriction:*` keys and `except`.
+    pub fn from_relation(relation: &Relation, profile: Profile) -> Option<Self> {
+        if !relation.tags.contains("type", "restriction") {
+            return None;
+        }
+        let value = profile
+            .restriction_keys()
+            .iter()
+            .filter_map(|key| relation.tags.get(*key))
+            .next_back()?;
+        let kind = if value.starts_with("no_") {
+            RestrictionKind::No
+        } else if value.starts_with("only_") {
+            RestrictionKind::Only
+        } else {
+            return None;
+        };
+        if let Some(except) = relation.tags.get("except") {
+            if except
+                .split(';')
+                .any(|mode| profile.restriction_modes().contains(&mode.trim()))
+            {
+                return None;
+            }
+        }
+
+        let mut from = None;
+        let mut to = None;
+        let mut via_node = None;
+        let mut via_ways = Vec::new();
+        for member in relation.refs.iter() {
+            match (member.role.as_str(), member.member) {
+                ("from", OsmId::Way(id)) if from.is_none() => from = Some(id),
+                ("to", OsmId::Way(id)) if to.is_none() => to = Some(id),
+                ("via", OsmId::Node(id)) => via_node = Some(id),
+                ("via", OsmId::Way(id)) => via_ways.push(id),
+                // Multiple from or to ways and members of other types are not supported.
+                ("from", _) | ("to", _) | ("via", _) => return None,
+                _ => {}
+            }
+        }
+        let via = match (via_node, via_ways.is_empty()) {
+            (Some(node), true) => Via::Node(node),
+            (None, false) => Via::Ways(via_ways),
+            _ => return None,
+        };
+        Some(TurnRestriction {
+            kind,
+            from: from?,
+            via,
+            to: to?,
+        })
+    }
+
+    /// Sequences of consecutive edges of `graph` that may not be driven because of this
+    /// restriction. Returns no sequences if the restriction does not fit the ways, e.g. if one
+    /// of them is missing or they do not touch.
+    fn forbidden_sequences(
+        &self,
+        ways: &HashMap<WayId, WayInfo>,
+        graph: &Graph,
+    ) -> Vec<Vec<EdgeIndex>> {
+        let (Some(from), Some(to)) = (ways.get(&self.from), ways.get(&self.to)) else {
+            return Vec::new();
+        };
+
+        // Nodes along the via ways in the order they are driven.
+        let mut via_path: Vec<NodeId> = Vec::new();
+        let last_via = match &self.via {
+            Via::Node(node) => *node,
+            Via::Ways(via_ways) => {
+                let mut prev = from;
+                for id in via_ways.iter() {
+                    let Some(way) = ways.get(id) else {
+                        return Vec::new();
+                    };
+                    let Some(segment) = traversal(prev, way) else {
+                        return Vec::new();
+                    };
+                    if via_path.last().is_some_and(|last| *last != segment[0]) {
+                        return Vec::new();
+                    }
+                    let skip = if via_path.is_empty() { 0 } else { 1 };
+                    via_path.extend(segment.into_iter().skip(skip));
+                    prev = way;
+                }
+                match traversal(prev, to) {
+                    Some(segment) if via_path.last() == Some(&segment[0]) => segment[0],
+                    _ => return Vec::new(),
+                }
+            }
+        };
+        let first_via = via_path.first().copied().unwrap_or(last_via);
+
+        let index = |id: NodeId| graph.index(id);
+        let Some(via_index) = index(first_via) else {
+            return Vec::new();
+        };
+        let mut via_edges: Vec<EdgeIndex> = Vec::new();
+        for pair in via_path.windows(2) {
+            match (index(pair[0]), index(pair[1])) {
+                (Some(a), Some(b)) => match graph.find_edge(a, b) {
+                    Some(edge) => via_edges.push(edge),
+                    None => return Vec::new(),
+                },
+                _ => return Vec::new(),
+            }
+        }
+        let Some(last_index) = index(last_via) else {
+            return Vec::new();
+        };
+
+        let from_edges: Vec<EdgeIndex> = adjacent_nodes(from, first_via)
+            .filter_map(index)
+            .filter_map(|node| graph.find_edge(node, via_index))
+            .collect();
+        let to_edges: Vec<EdgeIndex> = adjacent_nodes(to, last_via)
+            .filter_map(index)
+            .filter_map(|node| graph.find_edge(last_index, node))
+            .collect();
+        let turns: Vec<EdgeIndex> = match self.kind {
+            RestrictionKind::No => to_edges,
+            RestrictionKind::Only => {
+                if to_edges.is_empty() {
+                    return Vec::new();
+                }
+                graph
+                    .out_edges(last_index)
+                    .map(|edge| edge.id)
+                    .filter(|edge| !to_edges.contains(edge))
+                    .collect()
+            }
+        };
+
+        let mut sequences = Vec::new();
+        for from_edge in from_edges.iter() {
+            for turn in turns.iter() {
+                let mut sequence = vec![*from_edge];
+                sequence.extend(via_edges.iter().copied());
+                sequence.push(*turn);
+                sequences.push(sequence);
+            }
+        }
+        sequences
+    }
+}
+
+/// Nodes of `way` next to `node`.
+fn adjacent_nodes(way: &WayInfo, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    way.nodes
+        .iter()
+        .enumerate()
+        .filter(move |(_, id)| **id == node)
+        .flat_map(move |(i, _)| {
+            let prev = i.checked_sub(1).map(|j| way.nodes[j]);
+            let next = way.nodes.get(i + 1).copied();
+            prev.into_iter().chain(next)
+        })
+}
+
+/// Nodes of `way` traversed when entering it from `prev`, which has to end at one of its
+/// ends, and going to its other end.
+fn traversal(prev: &WayInfo, way: &WayInfo) -> Option<Vec<NodeId>> {
+    let (first, last) = (*way.nodes.first()?, *way.nodes.last()?);
+    let (prev_first, prev_last) = (*prev.nodes.first()?, *prev.nodes.last()?);
+    if first == prev_first || first == prev_last {
+        Some(way.nodes.clone())
+    } else if last == prev_first || last == prev_last {
+        Some(way.nodes.iter().rev().copied().collect())
+    } else {
+        None
+    }
+}
+
+/// State of a turn-aware search: the longest suffix of the edges driven so far that is a
+/// proper prefix of a forbidden sequence.
+pub type TurnState = u32;
+
+/// State of a search that is not in the middle of any restricted maneuver.
+pub const UNRESTRICTED: TurnState = 0;
+
+/// Forbidden edge sequences of a graph, matched during the search like strings against a
+/// set of patterns. Most turns are unrestricted, so a search only needs to track more than
+/// one label per node around restricted intersections.
+#[derive(Debug, Clone)]
+pub struct TurnRestrictions {
+    /// Edge sequence of every state, the empty one for `UNRESTRICTED`.
+    states: Vec<Vec<EdgeIndex>>,
+    prefixes: HashMap<Vec<EdgeIndex>, TurnState>,
+    forbidden: HashSet<Vec<EdgeIndex>>,
+    /// First edges of all forbidden sequences.
+    starts: HashSet<EdgeIndex>,
+}
+
+impl Default for TurnRestrictions {
+    fn default() -> Self {
+        TurnRestrictions {
+            states: vec![Vec::new()],
+            prefixes: HashMap::from([(Vec::new(), UNRESTRICTED)]),
+            forbidden: HashSet::new(),
+            starts: HashSet::new(),
+        }
+    }
+}
+
+impl TurnRestrictions {
+    /// Converts `restrictions` on `ways` into forbidden edge sequences of `graph`.
+    pub fn new(
+        restrictions: &[TurnRestriction],
+        ways: &HashMap<WayId, WayInfo>,
+        graph: &Graph,
+    ) -> Self {
+        TurnRestrictions::from_sequences(
+            restrictions
+                .iter()
+                .flat_map(|restriction| restriction.forbidden_sequences(ways, graph)),
+        )
+    }
+
+    /// Builds the matcher of `forbidden` edge sequences, each of at least two edges.
+    fn from_sequences(forbidden: impl IntoIterator<Item = Vec<EdgeIndex>>) -> Self {
+        let mut result = TurnRestrictions::default();
+        for sequence in forbidden {
+            for len in 1..sequence.len() {
+                let prefix = &sequence[..len];
+                if !result.prefixes.contains_key(prefix) {
+                    result
+                        .prefixes
+                        .insert(prefix.to_vec(), result.states.len() as TurnState);
+                    result.states.push(prefix.to_vec());
+                }
+            }
+            result.starts.insert(sequence[0]);
+            result.forbidden.insert(sequence);
+        }
+        result
+    }
+
+    /// Forbidden sequences with every edge replaced by `new_edge`, leaving out the sequences
+    /// with an edge it maps to `None`.
+    pub(crate) fn remap(&self, new_edge: impl Fn(EdgeIndex) -> Option<EdgeIndex>) -> Self {
+        TurnRestrictions::from_sequences(
+            self.forbidden
+                .iter()
+                .filter_map(|sequence| sequence.iter().map(|edge| new_edge(*edge)).collect()),
+        )
+    }
+
+    /// All edges of the forbidden sequences.
+    pub(crate) fn edges(&self) -> impl Iterator<Item = EdgeIndex> + '_ {
+        self.forbidden.iter().flatten().copied()
+    }
+
+    /// Number of forbidden edge sequences.
+    pub fn sequence_count(&self) -> usize {
+        self.forbidden.len()
+    }
+
+    /// State after driving along `edge` in `state`, or `None` if that completes a forbidden
+    /// sequence.
+    pub fn next(&self, state: TurnState, edge: EdgeIndex) -> Option<TurnState> {
+        if state == UNRESTRICTED && !self.starts.contains(&edge) {
+            return Some(UNRESTRICTED);
+        }
+        let mut sequence = self.states[state as usize].clone();
+        sequence.push(edge);
+        // Every forbidden sequence ending here starts within the current state, so checking
+        // the suffixes of the extended state is enough.
+        for start in 0..sequence.len() {
+            if self.forbidden.contains(&sequence[start..]) {
+                return None;
+            }
+        }
+        let next = (0..sequence.len())
+            .find_map(|start| self.prefixes.get(&sequence[start..]).copied())
+            .unwrap_or(UNRESTRICTED);
+        Some(next)
+    }
+}
+
+impl Encode for TurnRestrictions {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        let forbidden: Vec<Vec<EdgeIndex>> = self.forbidden.iter().cloned().collect();
+        forbidden.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        let forbidden: Vec<Vec<EdgeIndex>> = Encode::decode(decoder)?;
+        if forbidden.iter().any(|sequence| sequence.len() < 2) {
+            return Err(corrupt("forbidden turn with less than two edges"));
+        }
+        Ok(TurnRestrictions::from_sequences(forbidden))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use osmpbfreader::NodeId;
+    use osmpbfreader::WayId;
+
+    use super::RestrictionKind;
+    use super::TurnRestriction;
+    use super::TurnRestrictions;
+    use super::Via;
+    use super::UNRESTRICTED;
+    use crate::graph::EdgeIndex;
+    use crate::graph::Graph;
+    use crate::map::fixture;
+    use crate::WayInfo;
+
+    /// Two way ways given by their ids and nodes, and the graph of their edges. Node `n` is
+    /// at `coordinates[n - 1]`.
+    fn network(
+        coordinates: &[(f64, f64)],
+        ways: &[(i64, &[i64])],
+    ) -> (HashMap<WayId, WayInfo>, Graph) {
+        let ways = fixture::ways(ways);
+        let graph = fixture::two_way_graph(&fixture::nodes(coordinates), &ways);
+        (ways, graph)
+    }
+
+    fn edge(graph: &Graph, from: i64, to: i64) -> EdgeIndex {
+        let index = |id| graph.index(NodeId(id)).unwrap();
+        graph.find_edge(index(from), index(to)).unwrap()
+    }
+
+    fn sorted(mut sequences: Vec<Vec<EdgeIndex>>) -> Vec<Vec<EdgeIndex>> {
+        sequences.sort();
+        sequences
+    }
+
+    /// Crossing at node 1 of ways 10 from the west, 11 to the east, 12 to the north and 13
+    /// from the south.
+    fn crossing() -> (HashMap<WayId, WayInfo>, Graph) {
+        let coordinates = [
+            (0.0, 0.0),
+            (0.0, -0.001),
+            (0.0, 0.001),
+            (0.001, 0.0),
+            (-0.001, 0.0),
+        ];
+        network(
+            &coordinates,
+            &[(10, &[2, 1]), (11, &[1, 3]), (12, &[1, 4]), (13, &[5, 1])],
+        )
+    }
+
+    #[test]
+    fn via_node_no_turn() {
+        let (ways, graph) = crossing();
+        let restriction = TurnRestriction {
+            kind: RestrictionKind::No,
+            from: WayId(10),
+            via: Via::Node(NodeId(1)),
+            to: WayId(12),
+        };
+        assert_eq!(
+            restriction.forbidden_sequences(&ways, &graph),
+            vec![vec![edge(&graph, 2, 1), edge(&graph, 1, 4)]]
+        );
+    }
+
+    #[test]
+    fn via_node_only_turn() {
+        let (ways, graph) = crossing();
+        let restriction = TurnRestriction {
+            kind: RestrictionKind::Only,
+            from: WayId(10),
+            via: Via::Node(NodeId(1)),
+            to: WayId(11),
+        };
+        let from = edge(&graph, 2, 1);
+        let expected = [(1, 2), (1, 4), (1, 5)]
+            .iter()
+            .map(|(a, b)| vec![from, edge(&graph, *a, *b)])
+            .collect();
+        assert_eq!(
+            sorted(restriction.forbidden_sequences(&ways, &graph)),
+            sorted(expected)
+        );
+    }
+
+    #[test]
+    fn via_node_not_on_the_ways() {
+        let (ways, graph) = crossing();
+        let restriction = TurnRestriction {
+            kind: RestrictionKind::No,
+            from: WayId(10),
+            via: Via::Node(NodeId(3)),
+            to: WayId(12),
+        };
+        assert!(restriction.forbidden_sequences(&ways, &graph).is_empty());
+    }
+
+    #[test]
+    fn via_way_sequence() {
+        // Way 20 from 1 to 2, via way 21 between 2 and 3, way 22 from 3 to 4 and way 23 from
+        // 3 to 5.
+        let coordinates = [
+            (0.0, 0.0),
+            (0.0, 0.001),
+            (0.0, 0.002),
+            (0.001, 0.002),
+            (-0.001, 0.002),
+        ];
+        for via_nodes in [&[2, 3][..], &[3, 2][..]] {
+            let (ways, graph) = network(
+                &coordinates,
+                &[(20, &[1, 2]), (21, via_nodes), (22, &[3, 4]), (23, &[3, 5])],
+            );
+            let restriction = TurnRestriction {
+                kind: RestrictionKind::No,
+                from: WayId(20),
+                via: Via::Ways(vec![WayId(21)]),
+                to: WayId(22),
+            };
+            let expected = vec![vec![
+                edge(&graph, 1, 2),
+                edge(&graph, 2, 3),
+                edge(&graph, 3, 4),
+            ]];
+            assert_eq!(restriction.forbidden_sequences(&ways, &graph), expected);
+
+            let only = TurnRestriction {
+                kind: RestrictionKind::Only,
+                ..restriction
+            };
+            let through = [edge(&graph, 1, 2), edge(&graph, 2, 3)];
+            let expected = [(3, 2), (3, 5)]
+                .iter()
+                .map(|(a, b)| [&through[..], &[edge(&graph, *a, *b)]].concat())
+                .collect();
+            assert_eq!(
+                sorted(only.forbidden_sequences(&ways, &graph)),
+                sorted(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn next_matches_sequences() {
+        let restrictions =
+            TurnRestrictions::from_sequences([vec![0, 1], vec![2, 3, 4], vec![3, 6]]);
+        assert_eq!(restrictions.sequence_count(), 3);
+        assert_eq!(restrictions.next(UNRESTRICTED, 5), Some(UNRESTRICTED));
+
+        let after_0 = restrictions.next(UNRESTRICTED, 0).unwrap();
+        assert_ne!(after_0, UNRESTRICTED);
+        assert_eq!(restrictions.next(after_0, 1), None);
+        assert_eq!(restrictions.next(after_0, 5), Some(UNRESTRICTED));
+        // Starts a new sequence right after leaving another one.
+        let after_0_2 = restrictions.next(after_0, 2).unwrap();
+        assert_eq!(Some(after_0_2), restrictions.next(UNRESTRICTED, 2));
+
+        let after_2_3 = restrictions.next(after_0_2, 3).unwrap();
+        assert_eq!(restrictions.next(after_2_3, 4), None);
+        // The suffix 3 of 2, 3 is the start of the sequence 3, 6.
+        assert_eq!(restrictions.next(after_2_3, 6), None);
+        assert_eq!(restrictions.next(after_2_3, 5), Some(UNRESTRICTED));
+    }
+
+    #[test]
+    fn remap_drops_sequences_with_removed_edges() {
+        let restrictions = TurnRestrictions::from_sequences([vec![0, 1], vec![2, 3]]);
+        let remapped = restrictions.remap(|edge| if edge == 3 { None } else { Some(edge + 10) });
+        assert_eq!(remapped.sequence_count(), 1);
+        let state = remapped.next(UNRESTRICTED, 10).unwrap();
+        assert_eq!(remapped.next(state, 11), None);
+        assert_eq!(remapped.next(UNRESTRICTED, 0), Some(UNRESTRICTED));
+    }
+}
@@ -0,0 +1,410 @@
+use std::cell::OnceCell;
+use std::fmt;
+use std::str::FromStr;
+
+use osmpbfreader::NodeId;
+
+use crate::alt::LandmarkSelection;
+use crate::alt::Landmarks;
+use crate::ch::ContractionHierarchy;
+use crate::graph::EdgeIndex;
+use crate::graph::NodeIndex;
+use crate::graph::Weighting;
+use crate::routing::Path;
+use crate::routing::RouteEnds;
+use crate::routing::SearchObserver;
+use crate::snap::Snap;
+use crate::snap::SpatialIndex;
+use crate::Map;
+
+/// Point-to-point routing algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Dijkstra,
+    AStar,
+    BidirectionalDijkstra,
+    BidirectionalAStar,
+    ContractionHierarchy,
+    Alt,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 6] = [
+        Algorithm::Dijkstra,
+        Algorithm::AStar,
+        Algorithm::BidirectionalDijkstra,
+        Algorithm::BidirectionalAStar,
+        Algorithm::ContractionHierarchy,
+        Algorithm::Alt,
+    ];
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dijkstra" => Ok(Algorithm::Dijkstra),
+            "astar" => Ok(Algorithm::AStar),
+            "bidijkstra" => Ok(Algorithm::BidirectionalDijkstra),
+            "biastar" => Ok(Algorithm::BidirectionalAStar),
+            "ch" => Ok(Algorithm::ContractionHierarchy),
+            "alt" => Ok(Algorithm::Alt),
+            _ => Err(format!("unknown algorithm {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Algorithm::Dijkstra => "dijkstra",
+            Algorithm::AStar => "astar",
+            Algorithm::BidirectionalDijkstra => "bidijkstra",
+            Algorithm::BidirectionalAStar => "biastar",
+            Algorithm::ContractionHierarchy => "ch",
+            Algorithm::Alt => "alt",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Preprocessing data built earlier, e.g. read from a graph file.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessing {
+    pub hierarchy: Option<ContractionHierarchy>,
+    pub landmarks: Option<Landmarks>,
+}
+
+/// `Map` together with the preprocessing data of the algorithms that need it.
+pub struct Router<'a> {
+    map: &'a Map,
+    hierarchy: Option<ContractionHierarchy>,
+    landmarks: Option<Landmarks>,
+    /// Built on the first snapped query.
+    index: OnceCell<SpatialIndex>,
+}
+
+/// Node reached from or reaching a `Snap` over part of its edge, with that edge, `None` if
+/// the snap lies on the node, and the length and the duration of the part.
+type Access = (NodeIndex, Option<EdgeIndex>, f64, f64);
+
+impl<'a> Router<'a> {
+    /// Router answering queries with any of `algorithms`. Missing parts of `preprocessing`
+    /// are only built if needed, the landmarks as `landmark_count` landmarks picked by
+    /// `selection`.
+    pub fn new(
+        map: &'a Map,
+        preprocessing: Preprocessing,
+        algorithms: &[Algorithm],
+        landmark_count: usize,
+        selection: LandmarkSelection,
+    ) -> Self {
+        let Preprocessing {
+            mut hierarchy,
+            mut landmarks,
+        } = preprocessing;
+        if algorithms.contains(&Algorithm::ContractionHierarchy) && hierarchy.is_none() {
+            hierarchy = Some(ContractionHierarchy::new(map.graph()));
+        }
+        if algorithms.contains(&Algorithm::Alt) && landmarks.is_none() {
+            landmarks = Some(Landmarks::new(map.graph(), landmark_count, selection, 42));
+        }
+        Router {
+            map,
+            hierarchy,
+            landmarks,
+            index: OnceCell::new(),
+        }
+    }
+
+    pub fn map(&self) -> &Map {
+        self.map
+    }
+
+    /// Route from `from` to `to` found by `algorithm`, `None` if there is none, either node
+    /// is not part of the map or the router was not built for `algorithm`.
+    pub fn route(&self, algorithm: Algorithm, from: NodeId, to: NodeId) -> Option<Path> {
+        self.route_observed(algorithm, from, to, &mut ())
+    }
+
+    /// `route` reporting the steps of the search to `observer`.
+    pub fn route_observed(
+        &self,
+        algorithm: Algorithm,
+        from: NodeId,
+        to: NodeId,
+        observer: &mut impl SearchObserver,
+    ) -> Option<Path> {
+        let graph = self.map.graph();
+        let (from, to) = (graph.index(from)?, graph.index(to)?);
+        self.search(algorithm, from, to, RouteEnds::default(), observer)
+    }
+
+    /// Route from `from` to `to` found by `algorithm` that honours the turn restrictions,
+    /// including those involving the edges of `ends`.
+    ///
+    /// Bidirectional searches and contraction hierarchies cannot tell turn states apart.
+    /// Their routes are checked against the restrictions instead, see
+    /// `Graph::honour_restrictions`.
+    fn search(
+        &self,
+        algorithm: Algorithm,
+        from: NodeIndex,
+        to: NodeIndex,
+        ends: RouteEnds,
+        observer: &mut impl SearchObserver,
+    ) -> Option<Path> {
+        let graph = self.map.graph();
+        match algorithm {
+            Algorithm::Dijkstra => graph.guided_search(from, to, ends, |_| 0.0, observer),
+            Algorithm::AStar => {
+                let heuristic = |node| graph.weight_lower_bound(node, to);
+                graph.guided_search(from, to, ends, heuristic, observer)
+            }
+            Algorithm::Alt => {
+                let landmarks = self.landmarks.as_ref()?;
+                let heuristic = |node| landmarks.lower_bound(node, to);
+                graph.guided_search(from, to, ends, heuristic, observer)
+            }
+            Algorithm::BidirectionalDijkstra => {
+                graph.bidirectional_search(from, to, ends, |_| 0.0, observer)
+            }
+            Algorithm::BidirectionalAStar => {
+                let potential = graph.bidirectional_potential(from, to);
+                graph.bidirectional_search(from, to, ends, potential, observer)
+            }
+            Algorithm::ContractionHierarchy => {
+                let hierarchy = self.hierarchy.as_ref()?;
+                let path = hierarchy.shortest_path(from, to, observer);
+                graph.honour_restrictions(path, from, to, ends, observer)
+            }
+        }
+    }
+
+    pub fn index(&self) -> &SpatialIndex {
+        self.index
+            .get_or_init(|| SpatialIndex::new(self.map.graph()))
+    }
+
+    /// Point on the road closest to `lat`, `lon` within the largest strongly connected
+    /// component of the map.
+    pub fn snap(&self, lat: f64, lon: f64) -> Option<Snap> {
+        self.index().nearest_edge(lat, lon)
+    }
+
+    /// Route from `from` to `to` found by `algorithm`, which starts and ends in the middle
+    /// of their edges. It is the best of the routes between the endpoints of the two edges
+    /// that can be reached in their directions, plus the parts of the edges on both ends.
+    /// Turn restrictions are honoured including the edges on both ends.
+    pub fn route_between(&self, algorithm: Algorithm, from: &Snap, to: &Snap) -> Option<Path> {
+        let graph = self.map.graph();
+        let weight = |length: f64, duration: f64| match graph.weighting() {
+            Weighting::Distance => length,
+            Weighting::Time => duration,
+        };
+        let mut best: Option<Path> = self.along_edge(from, to);
+        let mut settled = 0;
+        for (source, first, source_length, source_duration) in self.accesses(from, false) {
+            for (target, last, target_length, target_duration) in self.accesses(to, true) {
+                let ends = RouteEnds { first, last };
+                let Some(mut path) = self.search(algorithm, source, target, ends, &mut ()) else {
+                    continue;
+                };
+                settled += path.settled;
+                path.length += source_length + target_length;
+                path.duration += source_duration + target_duration;
+                if best.as_ref().is_none_or(|best| {
+                    weight(path.length, path.duration) < weight(best.length, best.duration)
+                }) {
+                    best = Some(path);
+                }
+            }
+        }
+        best.map(|path| Path { settled, ..path })
+    }
+
+    /// Edge opposite to `edge`, if there is one.
+    fn reverse_edge(&self, edge: EdgeIndex) -> Option<EdgeIndex> {
+        let graph = self.map.graph();
+        graph.find_edge(graph.head(edge), graph.tail(edge))
+    }
+
+    /// Nodes reachable from `snap` along its edge or the opposite one, or the nodes `snap`
+    /// is reachable from if `reverse` is set.
+    fn accesses(&self, snap: &Snap, reverse: bool) -> Vec<Access> {
+        let graph = self.map.graph();
+        let (tail, head) = (graph.tail(snap.edge), graph.head(snap.edge));
+        let part = |node: NodeIndex, edge: EdgeIndex, fraction: f64| {
+            (
+                node,
+                // Routes from or to a point on the node itself do not drive along the edge.
+                Some(edge).filter(|_| fraction > 0.0),
+                fraction * graph.length(edge),
+                fraction * graph.duration(edge),
+            )
+        };
+        let (forward, backward) = if reverse {
+            ((tail, snap.fraction), (head, 1.0 - snap.fraction))
+        } else {
+            ((head, 1.0 - snap.fraction), (tail, snap.fraction))
+        };
+        let mut accesses = vec![part(forward.0, snap.edge, forward.1)];
+        match self.reverse_edge(snap.edge) {
+            Some(edge) => accesses.push(part(backward.0, edge, backward.1)),
+            // A point on the node itself is reached without the opposite edge.
+            None if backward.1 == 0.0 => accesses.push((backward.0, None, 0.0, 0.0)),
+            None => {}
+        }
+        accesses
+    }
+
+    /// Route from `from` to `to` staying on their edge, if they are on the same road
+    /// segment in an order allowed by its direction. Its nodes are the tail and the head of
+    /// the edge driven along.
+    fn along_edge(&self, from: &Snap, to: &Snap) -> Option<Path> {
+        let graph = self.map.graph();
+        // Position of `to` along the edge of `from`.
+        let to_fraction = if to.edge == from.edge {
+            to.fraction
+        } else if Some(to.edge) == self.reverse_edge(from.edge) {
+            1.0 - to.fraction
+        } else {
+            return None;
+        };
+        let (edge, fraction) = if to_fraction >= from.fraction {
+            (from.edge, to_fraction - from.fraction)
+        } else {
+            (self.reverse_edge(from.edge)?, from.fraction - to_fraction)
+        };
+        Some(Path {
+            length: fraction * graph.length(edge),
+            duration: fraction * graph.duration(edge),
+            nodes: vec![graph.id(graph.tail(edge)), graph.id(graph.head(edge))],
+            settled: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use osmpbfreader::NodeId;
+    use osmpbfreader::WayId;
+
+    use super::Algorithm;
+    use super::Preprocessing;
+    use super::Router;
+    use crate::alt::LandmarkSelection;
+    use crate::graph::Weighting;
+    use crate::map::fixture;
+    use crate::profile::Profile;
+    use crate::restriction::RestrictionKind;
+    use crate::restriction::TurnRestriction;
+    use crate::restriction::Via;
+    use crate::Map;
+
+    /// Crossing at node 1 of ways 10 from node 2 in the west, 11 to node 3 in the east and
+    /// 12 to node 4 in the north, with way 13 from 3 to 4 and no left turn from 10 to 12.
+    fn restricted_crossing() -> Map {
+        let nodes = fixture::nodes(&[(0.0, 0.0), (0.0, -0.001), (0.0, 0.001), (0.001, 0.0)]);
+        let ways = fixture::ways(&[(10, &[2, 1]), (11, &[1, 3]), (12, &[1, 4]), (13, &[3, 4])]);
+        let restriction = TurnRestriction {
+            kind: RestrictionKind::No,
+            from: WayId(10),
+            via: Via::Node(NodeId(1)),
+            to: WayId(12),
+        };
+        Map::new(
+            nodes,
+            ways,
+            &[restriction],
+            Profile::Car,
+            Weighting::Distance,
+        )
+    }
+
+    #[test]
+    fn all_algorithms_honour_turn_restrictions() {
+        let map = restricted_crossing();
+        let router = Router::new(
+            &map,
+            Preprocessing::default(),
+            &Algorithm::ALL,
+            2,
+            LandmarkSelection::Avoid,
+        );
+        let detour: Vec<NodeId> = [2, 1, 3, 4].iter().map(|id| NodeId(*id)).collect();
+        for algorithm in Algorithm::ALL {
+            let path = router.route(algorithm, NodeId(2), NodeId(4)).unwrap();
+            assert_eq!(path.nodes, detour, "{}", algorithm);
+            // The other way round the turn is allowed.
+            let path = router.route(algorithm, NodeId(4), NodeId(2)).unwrap();
+            assert_eq!(path.nodes.len(), 3, "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn bidirectional_searches_honour_turn_restrictions() {
+        let map = restricted_crossing();
+        let detour: Vec<NodeId> = [2, 1, 3, 4].iter().map(|id| NodeId(*id)).collect();
+        let path = map
+            .bidirectional_shortest_path(NodeId(2), NodeId(4))
+            .unwrap();
+        assert_eq!(path.nodes, detour);
+        let path = map.bidirectional_astar(NodeId(2), NodeId(4)).unwrap();
+        assert_eq!(path.nodes, detour);
+    }
+
+    #[test]
+    fn snapped_routes_honour_turn_restrictions() {
+        let map = restricted_crossing();
+        let router = Router::new(
+            &map,
+            Preprocessing::default(),
+            &Algorithm::ALL,
+            2,
+            LandmarkSelection::Avoid,
+        );
+        // Halfway along way 10 and way 12.
+        let from = router.snap(0.0, -0.0005).unwrap();
+        let to = router.snap(0.0005, 0.0).unwrap();
+        let graph = map.graph();
+        // Turning left at 1 would only drive the half of each edge.
+        let forbidden = graph.length(graph.find_edge(0, 3).unwrap());
+        let best = router
+            .route_between(Algorithm::Dijkstra, &from, &to)
+            .unwrap()
+            .length;
+        assert!(best > 2.0 * forbidden);
+        for algorithm in Algorithm::ALL {
+            let path = router.route_between(algorithm, &from, &to).unwrap();
+            assert_ne!(path.nodes.get(1), Some(&NodeId(4)), "{}", algorithm);
+            assert!((path.length - best).abs() < 1e-6, "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn routes_along_a_single_edge() {
+        let map = restricted_crossing();
+        let router = Router::new(
+            &map,
+            Preprocessing::default(),
+            &Algorithm::ALL,
+            2,
+            LandmarkSelection::Avoid,
+        );
+        // Both on way 11, a fifth and seven tenths of the way from node 1 to node 3.
+        let a = router.snap(0.0, 0.0002).unwrap();
+        let b = router.snap(0.0, 0.0007).unwrap();
+        let graph = map.graph();
+        let length = graph.length(graph.find_edge(0, 2).unwrap());
+        for algorithm in Algorithm::ALL {
+            let path = router.route_between(algorithm, &a, &b).unwrap();
+            assert_eq!(path.nodes, [NodeId(1), NodeId(3)], "{}", algorithm);
+            assert!((path.length - 0.5 * length).abs() < 1e-6, "{}", algorithm);
+            let path = router.route_between(algorithm, &b, &a).unwrap();
+            assert_eq!(path.nodes, [NodeId(3), NodeId(1)], "{}", algorithm);
+            assert!((path.length - 0.5 * length).abs() < 1e-6, "{}", algorithm);
+        }
+    }
+}
@@ -0,0 +1,629 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+
+use osmpbfreader::NodeId;
+
+use crate::graph::EdgeIndex;
+use crate::graph::Graph;
+use crate::graph::NodeIndex;
+use crate::graph::INVALID_NODE;
+use crate::restriction::TurnState;
+use crate::restriction::UNRESTRICTED;
+use crate::Map;
+
+/// Result of a point-to-point query.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Path {
+    /// Total length of the path in meters.
+    pub length: f64,
+    /// Total travel time of the path in seconds.
+    pub duration: f64,
+    /// The ordered list of nodes from the source to the target.
+    pub nodes: Vec<NodeId>,
+    /// Number of nodes settled by the search, useful for comparing algorithms.
+    pub settled: usize,
+}
+
+/// Receives the steps of a search while it runs, e.g. to animate it. Bidirectional searches
+/// report the steps of their backward search with `forward` unset.
+pub trait SearchObserver {
+    /// The distance of `node` became final.
+    fn settled(&mut self, node: NodeIndex, forward: bool);
+    /// The tentative distance of `head` was improved by the edge from `tail`, which puts
+    /// `head` on the frontier of the search.
+    fn relaxed(&mut self, tail: NodeIndex, head: NodeIndex, forward: bool);
+}
+
+/// Observer of the searches nobody watches.
+impl SearchObserver for () {
+    fn settled(&mut self, _node: NodeIndex, _forward: bool) {}
+
+    fn relaxed(&mut self, _tail: NodeIndex, _head: NodeIndex, _forward: bool) {}
+}
+
+/// Edges driven right before the start and right after the end of a route that starts or
+/// ends in the middle of an edge. They take part in the turn restrictions of the route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct RouteEnds {
+    pub first: Option<EdgeIndex>,
+    pub last: Option<EdgeIndex>,
+}
+
+/// Entry of the priority queue, ordered so that `BinaryHeap` pops the smallest cost first.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) struct State<N = NodeIndex> {
+    pub cost: f64,
+    pub node: N,
+}
+
+impl<N: Ord> Eq for State<N> {}
+
+impl<N: Ord> Ord for State<N> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+impl<N: Ord> PartialOrd for State<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// State of one search direction, with arrays indexed by `NodeIndex`.
+struct SearchSide {
+    dist: Vec<f64>,
+    parents: Vec<NodeIndex>,
+    parent_edges: Vec<EdgeIndex>,
+    settled: Vec<bool>,
+    settled_count: usize,
+    queue: BinaryHeap<State>,
+}
+
+impl SearchSide {
+    fn new(node_count: usize, start: NodeIndex, key: f64) -> Self {
+        let mut side = SearchSide {
+            dist: vec![f64::INFINITY; node_count],
+            parents: vec![INVALID_NODE; node_count],
+            parent_edges: vec![0; node_count],
+            settled: vec![false; node_count],
+            settled_count: 0,
+            queue: BinaryHeap::new(),
+        };
+        side.dist[start as usize] = 0.0;
+        side.queue.push(State {
+            cost: key,
+            node: start,
+        });
+        side
+    }
+
+    fn min_key(&self) -> f64 {
+        self.queue.peek().map_or(f64::INFINITY, |state| state.cost)
+    }
+
+    /// Pops the next node that is not settled yet and marks it as settled.
+    fn settle_next(&mut self) -> Option<NodeIndex> {
+        while let Some(State { node, .. }) = self.queue.pop() {
+            if !self.settled[node as usize] {
+                self.settled[node as usize] = true;
+                self.settled_count += 1;
+                return Some(node);
+            }
+        }
+        None
+    }
+
+    /// Updates the tentative distance of `node` if going through `edge` from `parent` is
+    /// shorter. Returns whether it was.
+    fn relax(&mut self, parent: NodeIndex, edge: EdgeIndex, node: NodeIndex, cost: f64) -> bool {
+        if cost < self.dist[node as usize] {
+            self.dist[node as usize] = cost;
+            self.parents[node as usize] = parent;
+            self.parent_edges[node as usize] = edge;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Adds a label beyond the nodes of the graph, for reaching a node in a restricted turn
+    /// state, and returns its index.
+    fn add_label(&mut self) -> NodeIndex {
+        self.dist.push(f64::INFINITY);
+        self.parents.push(INVALID_NODE);
+        self.parent_edges.push(0);
+        self.settled.push(false);
+        (self.dist.len() - 1) as NodeIndex
+    }
+
+    /// Nodes and edges on the search tree path from the root to `to`.
+    fn unpack_path(&self, to: NodeIndex) -> (Vec<NodeIndex>, Vec<EdgeIndex>) {
+        let mut nodes = vec![to];
+        let mut edges = Vec::new();
+        let mut curr = to;
+        while self.parents[curr as usize] != INVALID_NODE {
+            edges.push(self.parent_edges[curr as usize]);
+            curr = self.parents[curr as usize];
+            nodes.push(curr);
+        }
+        nodes.reverse();
+        edges.reverse();
+        (nodes, edges)
+    }
+}
+
+impl Graph {
+    /// Runs Dijkstra from `from` and returns the shortest path to `to`, or `None` if `to`
+    /// is unreachable.
+    pub fn shortest_path(&self, from: NodeIndex, to: NodeIndex) -> Option<Path> {
+        self.shortest_path_observed(from, to, &mut ())
+    }
+
+    /// `shortest_path` reporting its steps to `observer`.
+    pub fn shortest_path_observed(
+        &self,
+        from: NodeIndex,
+        to: NodeIndex,
+        observer: &mut impl SearchObserver,
+    ) -> Option<Path> {
+        self.guided_search(from, to, RouteEnds::default(), |_| 0.0, observer)
+    }
+
+    /// Runs A* from `from` to `to` using the great-circle lower bound on the weight to `to`
+    /// as the heuristic. The heuristic is admissible and consistent, so the result is the
+    /// same as the one of `shortest_path`.
+    pub fn astar(&self, from: NodeIndex, to: NodeIndex) -> Option<Path> {
+        self.astar_observed(from, to, &mut ())
+    }
+
+    /// `astar` reporting its steps to `observer`.
+    pub fn astar_observed(
+        &self,
+        from: NodeIndex,
+        to: NodeIndex,
+        observer: &mut impl SearchObserver,
+    ) -> Option<Path> {
+        let heuristic = |node| self.weight_lower_bound(node, to);
+        self.guided_search(from, to, RouteEnds::default(), heuristic, observer)
+    }
+
+    /// Runs a full Dijkstra from `from`, following the edges backwards if `reverse` is set.
+    /// Returns the distances and parents of all nodes, `INFINITY` and `INVALID_NODE` for the
+    /// unreachable ones.
+    pub(crate) fn shortest_path_tree(
+        &self,
+        from: NodeIndex,
+        reverse: bool,
+    ) -> (Vec<f64>, Vec<NodeIndex>) {
+        let mut side = SearchSide::new(self.node_count(), from, 0.0);
+        while let Some(node) = side.settle_next() {
+            let cost = side.dist[node as usize];
+            for edge in self.edges(node, reverse) {
+                let new_cost = cost + edge.weight;
+                if side.relax(node, edge.id, edge.node, new_cost) {
+                    side.queue.push(State {
+                        cost: new_cost,
+                        node: edge.node,
+                    });
+                }
+            }
+        }
+        (side.dist, side.parents)
+    }
+
+    /// Search shared by Dijkstra and A*. Nodes are popped in the order of their distance
+    /// from `from` plus `heuristic`, which has to be consistent.
+    ///
+    /// The search honours the turn restrictions of the graph. A node reached in the middle
+    /// of a restricted maneuver gets an extra label for every such turn state, while labels
+    /// of unrestricted states are the nodes themselves, which is also what `observer` gets.
+    /// The edges of `ends` are part of the route for the restrictions, so the search starts
+    /// in the state after `ends.first` and only stops at `to` if `ends.last` may follow.
+    pub(crate) fn guided_search<H, O>(
+        &self,
+        from: NodeIndex,
+        to: NodeIndex,
+        ends: RouteEnds,
+        heuristic: H,
+        observer: &mut O,
+    ) -> Option<Path>
+    where
+        H: Fn(NodeIndex) -> f64,
+        O: SearchObserver,
+    {
+        let node_count = self.node_count();
+        let restrictions = self.turn_restrictions();
+        let mut side = SearchSide::new(node_count, from, heuristic(from));
+        let mut restricted_labels: HashMap<(NodeIndex, TurnState), NodeIndex> = HashMap::new();
+        let mut label_states: Vec<(NodeIndex, TurnState)> = Vec::new();
+
+        // A single edge never completes a forbidden sequence.
+        let start_state = ends
+            .first
+            .and_then(|edge| restrictions.next(UNRESTRICTED, edge))
+            .unwrap_or(UNRESTRICTED);
+        if start_state != UNRESTRICTED {
+            // The route starts in the middle of a restricted maneuver.
+            side.dist[from as usize] = f64::INFINITY;
+            side.queue.clear();
+            let label = side.add_label();
+            restricted_labels.insert((from, start_state), label);
+            label_states.push((from, start_state));
+            side.dist[label as usize] = 0.0;
+            side.queue.push(State {
+                cost: heuristic(from),
+                node: label,
+            });
+        }
+
+        while let Some(label) = side.settle_next() {
+            let cost = side.dist[label as usize];
+            let (node, state) = if (label as usize) < node_count {
+                (label, UNRESTRICTED)
+            } else {
+                label_states[label as usize - node_count]
+            };
+            observer.settled(node, true);
+            let may_end = ends
+                .last
+                .is_none_or(|edge| restrictions.next(state, edge).is_some());
+            if node == to && may_end {
+                let (labels, edges) = side.unpack_path(label);
+                let nodes: Vec<NodeIndex> = labels
+                    .iter()
+                    .map(|label| match (*label as usize).checked_sub(node_count) {
+                        Some(i) => label_states[i].0,
+                        None => *label,
+                    })
+                    .collect();
+                return Some(self.make_path(&nodes, &edges, side.settled_count));
+            }
+
+            for edge in self.out_edges(node) {
+                let Some(next_state) = restrictions.next(state, edge.id) else {
+                    continue;
+                };
+                let next = if next_state == UNRESTRICTED {
+                    edge.node
+                } else {
+                    *restricted_labels
+                        .entry((edge.node, next_state))
+                        .or_insert_with(|| {
+                            label_states.push((edge.node, next_state));
+                            side.add_label()
+                        })
+                };
+                let new_cost = cost + edge.weight;
+                if side.relax(label, edge.id, next, new_cost) {
+                    observer.relaxed(node, edge.node, true);
+                    side.queue.push(State {
+                        cost: new_cost + heuristic(edge.node),
+                        node: next,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Runs Dijkstra simultaneously from `from` forwards and from `to` backwards. Routes
+    /// making a forbidden turn are replaced by the one of `astar`, see `bidirectional_search`.
+    pub fn bidirectional_shortest_path(&self, from: NodeIndex, to: NodeIndex) -> Option<Path> {
+        self.bidirectional_shortest_path_observed(from, to, &mut ())
+    }
+
+    /// `bidirectional_shortest_path` reporting its steps to `observer`.
+    pub fn bidirectional_shortest_path_observed(
+        &self,
+        from: NodeIndex,
+        to: NodeIndex,
+        observer: &mut impl SearchObserver,
+    ) -> Option<Path> {
+        self.bidirectional_search(from, to, RouteEnds::default(), |_| 0.0, observer)
+    }
+
+    /// Bidirectional A* using the average of the forward and backward great-circle
+    /// potentials, `(h(v, to) - h(from, v)) / 2` for the forward search and its negation for
+    /// the backward one, where `h` is `Graph::weight_lower_bound`. Both are consistent, so
+    /// the usual bidirectional stopping criterion still holds. Routes making a forbidden turn
+    /// are replaced by the one of `astar`, see `bidirectional_search`.
+    pub fn bidirectional_astar(&self, from: NodeIndex, to: NodeIndex) -> Option<Path> {
+        self.bidirectional_astar_observed(from, to, &mut ())
+    }
+
+    /// `bidirectional_astar` reporting its steps to `observer`.
+    pub fn bidirectional_astar_observed(
+        &self,
+        from: NodeIndex,
+        to: NodeIndex,
+        observer: &mut impl SearchObserver,
+    ) -> Option<Path> {
+        let potential = self.bidirectional_potential(from, to);
+        self.bidirectional_search(from, to, RouteEnds::default(), potential, observer)
+    }
+
+    /// Potential of the bidirectional A* search from `from` to `to`.
+    pub(crate) fn bidirectional_potential(
+        &self,
+        from: NodeIndex,
+        to: NodeIndex,
+    ) -> impl Fn(NodeIndex) -> f64 + '_ {
+        move |node| (self.weight_lower_bound(node, to) - self.weight_lower_bound(from, node)) / 2.0
+    }
+
+    /// Bidirectional search shared by Dijkstra and A*. The forward search orders nodes by
+    /// `d(from, v) + potential(v)` and the backward one by `d(v, to) - potential(v)`, so
+    /// the search can stop as soon as the sum of both minimum keys reaches the best
+    /// path found so far.
+    ///
+    /// The node based labels of the two searches cannot tell apart the turn states they meet
+    /// in, so the route is checked against the turn restrictions afterwards, including those
+    /// involving the edges of `ends`, see `honour_restrictions`.
+    pub(crate) fn bidirectional_search<P, O>(
+        &self,
+        from: NodeIndex,
+        to: NodeIndex,
+        ends: RouteEnds,
+        potential: P,
+        observer: &mut O,
+    ) -> Option<Path>
+    where
+        P: Fn(NodeIndex) -> f64,
+        O: SearchObserver,
+    {
+        let mut forward = SearchSide::new(self.node_count(), from, potential(from));
+        let mut backward = SearchSide::new(self.node_count(), to, -potential(to));
+        let mut best = if from == to { 0.0 } else { f64::INFINITY };
+        let mut meeting = from;
+
+        while forward.min_key() + backward.min_key() < best {
+            let is_forward = forward.min_key() <= backward.min_key();
+            let (side, other) = if is_forward {
+                (&mut forward, &backward)
+            } else {
+                (&mut backward, &forward)
+            };
+
+            let Some(node) = side.settle_next() else {
+                break;
+            };
+            observer.settled(node, is_forward);
+            let cost = side.dist[node as usize];
+            for edge in self.edges(node, !is_forward) {
+                let new_cost = cost + edge.weight;
+                if side.relax(node, edge.id, edge.node, new_cost) {
+                    observer.relaxed(node, edge.node, is_forward);
+                    let key = if is_forward {
+                        new_cost + potential(edge.node)
+                    } else {
+                        new_cost - potential(edge.node)
+                    };
+                    side.queue.push(State {
+                        cost: key,
+                        node: edge.node,
+                    });
+                }
+                let through = new_cost + other.dist[edge.node as usize];
+                if through < best {
+                    best = through;
+                    meeting = edge.node;
+                }
+            }
+        }
+
+        if best == f64::INFINITY {
+            return None;
+        }
+        let (mut nodes, mut edges) = forward.unpack_path(meeting);
+        let (to_target, to_target_edges) = backward.unpack_path(meeting);
+        nodes.extend(to_target.into_iter().rev().skip(1));
+        edges.extend(to_target_edges.into_iter().rev());
+        let path = self.make_path(
+            &nodes,
+            &edges,
+            forward.settled_count + backward.settled_count,
+        );
+        self.honour_restrictions(Some(path), from, to, ends, observer)
+    }
+
+    /// `path` from `from` to `to` found by a search ignoring turn restrictions if it honours
+    /// them, including those involving the edges of `ends`, and otherwise the route of A*,
+    /// which honours them. The unrestricted route is never longer, so an allowed one is also
+    /// the best allowed route. The nodes settled by both searches are counted.
+    pub(crate) fn honour_restrictions(
+        &self,
+        path: Option<Path>,
+        from: NodeIndex,
+        to: NodeIndex,
+        ends: RouteEnds,
+        observer: &mut impl SearchObserver,
+    ) -> Option<Path> {
+        let path = path?;
+        let nodes: Option<Vec<NodeIndex>> = path.nodes.iter().map(|id| self.index(*id)).collect();
+        if nodes.is_some_and(|nodes| self.allows(&nodes, ends)) {
+            return Some(path);
+        }
+        let heuristic = |node| self.weight_lower_bound(node, to);
+        let mut restricted = self.guided_search(from, to, ends, heuristic, observer)?;
+        restricted.settled += path.settled;
+        Some(restricted)
+    }
+
+    /// Whether driving through `nodes` with the edges of `ends` before and after them
+    /// completes no forbidden sequence. Consecutive nodes are connected by the edge returned
+    /// by `find_edge`.
+    pub(crate) fn allows(&self, nodes: &[NodeIndex], ends: RouteEnds) -> bool {
+        let restrictions = self.turn_restrictions();
+        if restrictions.sequence_count() == 0 {
+            return true;
+        }
+        let mut edges = Vec::with_capacity(nodes.len() + 1);
+        edges.extend(ends.first);
+        for pair in nodes.windows(2) {
+            match self.find_edge(pair[0], pair[1]) {
+                Some(edge) => edges.push(edge),
+                None => return false,
+            }
+        }
+        edges.extend(ends.last);
+        let mut state = UNRESTRICTED;
+        for edge in edges {
+            match restrictions.next(state, edge) {
+                Some(next) => state = next,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Builds the `Path` going through `nodes` along `edges`.
+    pub(crate) fn make_path(
+        &self,
+        nodes: &[NodeIndex],
+        edges: &[EdgeIndex],
+        settled: usize,
+    ) -> Path {
+        Path {
+            length: edges.iter().map(|edge| self.length(*edge)).sum(),
+            duration: edges.iter().map(|edge| self.duration(*edge)).sum(),
+            nodes: nodes.iter().map(|node| self.id(*node)).collect(),
+            settled,
+        }
+    }
+}
+
+impl Map {
+    /// Runs Dijkstra from `from` and returns the shortest path to `to`, or `None` if `to`
+    /// is unreachable or either of the nodes is not part of the map.
+    pub fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        let graph = self.graph();
+        graph.shortest_path(graph.index(from)?, graph.index(to)?)
+    }
+
+    /// A* with the great-circle distance heuristic, see `Graph::astar`.
+    pub fn astar(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        let graph = self.graph();
+        graph.astar(graph.index(from)?, graph.index(to)?)
+    }
+
+    /// Bidirectional Dijkstra, see `Graph::bidirectional_shortest_path`.
+    pub fn bidirectional_shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        let graph = self.graph();
+        graph.bidirectional_shortest_path(graph.index(from)?, graph.index(to)?)
+    }
+
+    /// Bidirectional A*, see `Graph::bidirectional_astar`.
+    pub fn bidirectional_astar(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        let graph = self.graph();
+        graph.bidirectional_astar(graph.index(from)?, graph.index(to)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use osmpbfreader::NodeId;
+
+    use super::Path;
+    use crate::graph::Graph;
+    use crate::graph::NodeIndex;
+    use crate::graph::Weighting;
+    use crate::map::fixture;
+
+    type Search = fn(&Graph, NodeIndex, NodeIndex) -> Option<Path>;
+
+    const SEARCHES: [(&str, Search); 4] = [
+        ("dijkstra", Graph::shortest_path),
+        ("astar", Graph::astar),
+        ("bidijkstra", Graph::bidirectional_shortest_path),
+        ("biastar", Graph::bidirectional_astar),
+    ];
+
+    /// One way edge from 1 to 2, two way edge between 2 and 3, and node 4 without edges.
+    /// Node `n` has the index `n - 1`.
+    fn one_way_graph() -> Graph {
+        let nodes = fixture::nodes(&[(0.0, 0.0), (0.0, 0.001), (0.0, 0.002), (0.001, 0.0)]);
+        let edges: Vec<(NodeId, NodeId, f64)> = [(1, 2), (2, 3), (3, 2)]
+            .iter()
+            .map(|(a, b)| (NodeId(*a), NodeId(*b), 50.0))
+            .collect();
+        Graph::new(&nodes, &edges, Weighting::Distance)
+    }
+
+    fn ids(nodes: &[i64]) -> Vec<NodeId> {
+        nodes.iter().map(|id| NodeId(*id)).collect()
+    }
+
+    #[test]
+    fn one_way_edges() {
+        let graph = one_way_graph();
+        for (name, search) in SEARCHES {
+            assert_eq!(
+                search(&graph, 0, 2).unwrap().nodes,
+                ids(&[1, 2, 3]),
+                "{}",
+                name
+            );
+            assert!(search(&graph, 2, 0).is_none(), "{}", name);
+            assert!(search(&graph, 1, 0).is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn unreachable_targets() {
+        let graph = one_way_graph();
+        for (name, search) in SEARCHES {
+            assert!(search(&graph, 0, 3).is_none(), "{}", name);
+            assert!(search(&graph, 3, 0).is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn same_source_and_target() {
+        let graph = one_way_graph();
+        for (name, search) in SEARCHES {
+            for node in 0..graph.node_count() as NodeIndex {
+                let path = search(&graph, node, node).unwrap();
+                assert_eq!(path.nodes, vec![graph.id(node)], "{}", name);
+                assert_eq!(path.length, 0.0, "{}", name);
+                assert_eq!(path.duration, 0.0, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn all_searches_agree_on_a_grid() {
+        for weighting in [Weighting::Distance, Weighting::Time] {
+            let graph = fixture::grid_graph(6, weighting);
+            let weight = |path: &Path| match weighting {
+                Weighting::Distance => path.length,
+                Weighting::Time => path.duration,
+            };
+            for from in 0..graph.node_count() as NodeIndex {
+                for to in 0..graph.node_count() as NodeIndex {
+                    let expected = graph.shortest_path(from, to).map(|path| weight(&path));
+                    for (name, search) in SEARCHES {
+                        let path = search(&graph, from, to);
+                        let found = path.as_ref().map(weight);
+                        match (expected, found) {
+                            (Some(expected), Some(found)) => {
+                                assert!((expected - found).abs() < 1e-6, "{}", name)
+                            }
+                            (expected, found) => assert_eq!(expected, found, "{}", name),
+                        }
+                        // Consecutive nodes are joined by edges in the direction of travel.
+                        let nodes = path.map_or(Vec::new(), |path| path.nodes);
+                        assert!(nodes.windows(2).all(|pair| {
+                            let index = |id| graph.index(id).unwrap();
+                            graph.find_edge(index(pair[0]), index(pair[1])).is_some()
+                        }));
+                    }
+                }
+            }
+        }
+    }
+}
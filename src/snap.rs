@@ -0,0 +1,346 @@
+use crate::coordinate_distance;
+use crate::graph::EdgeIndex;
+use crate::graph::Graph;
+use crate::graph::NodeIndex;
+use crate::projection::Projection;
+use crate::BoundingBox;
+
+/// Cells of the grid are never smaller than this, in meters.
+const MIN_CELL_SIZE: f64 = 100.0;
+
+/// Point on an edge of the graph, the start or the end of a route between coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snap {
+    /// Edge the point lies on.
+    pub edge: EdgeIndex,
+    /// Position of the point along the edge, 0 at its tail and 1 at its head.
+    pub fraction: f64,
+    /// Latitude and longitude of the point in degrees.
+    pub lat: f64,
+    pub lon: f64,
+    /// Distance of the point from the snapped position in meters.
+    pub distance: f64,
+}
+
+impl Snap {
+    /// Point lying exactly on `node`, `None` if the node has no edges.
+    pub fn at_node(graph: &Graph, node: NodeIndex) -> Option<Snap> {
+        let (edge, fraction) = match graph.out_edges(node).next() {
+            Some(edge) => (edge.id, 0.0),
+            None => (graph.in_edges(node).next()?.id, 1.0),
+        };
+        let (lat, lon) = graph.coordinates(node);
+        Some(Snap {
+            edge,
+            fraction,
+            lat,
+            lon,
+            distance: 0.0,
+        })
+    }
+}
+
+/// Uniform grid over the edges of the largest strongly connected component, answering
+/// nearest node and nearest edge queries. Coordinates are projected to meters with an
+/// equirectangular projection centered on the graph, which is accurate enough to
+/// compare distances within a city or a region.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    /// Equirectangular projection centered on the indexed nodes.
+    projection: Projection,
+    /// Projected position of every node in meters, relative to the corner of the grid.
+    points: Vec<(f64, f64)>,
+    /// Projected corner of the grid in meters.
+    corner: (f64, f64),
+    cell_size: f64,
+    columns: usize,
+    rows: usize,
+    /// Segments of cell `c` are `first_segment[c]..first_segment[c + 1]`.
+    first_segment: Vec<u32>,
+    /// Edge, tail and head of the segments, in the order of the cells they overlap.
+    segments: Vec<(EdgeIndex, NodeIndex, NodeIndex)>,
+}
+
+impl SpatialIndex {
+    /// Indexes the edges of `graph` whose endpoints are in its largest strongly connected
+    /// component, so that there is a route between any two snapped points. The graph
+    /// only has edges usable by the profile it was built for. Of two opposite edges between
+    /// the same nodes only one is indexed.
+    pub fn new(graph: &Graph) -> Self {
+        let largest = graph.strong_components().largest();
+        let mut segments: Vec<(EdgeIndex, NodeIndex, NodeIndex)> = Vec::new();
+        for tail in 0..graph.node_count() as NodeIndex {
+            if !largest[tail as usize] {
+                continue;
+            }
+            for edge in graph.out_edges(tail) {
+                if !largest[edge.node as usize]
+                    || tail > edge.node && graph.find_edge(edge.node, tail).is_some()
+                {
+                    continue;
+                }
+                segments.push((edge.id, tail, edge.node));
+            }
+        }
+
+        let indexed: Vec<(f64, f64)> = (0..graph.node_count() as NodeIndex)
+            .filter(|node| largest[*node as usize])
+            .map(|node| graph.coordinates(node))
+            .collect();
+        let projection = match BoundingBox::enclosing(indexed.iter().copied()) {
+            Some(region) => Projection::Equirectangular { center_lat: 0.0 }.centered_on(&region),
+            None => Projection::Equirectangular { center_lat: 0.0 },
+        };
+        let project = |(lat, lon): (f64, f64)| projection.project(lat, lon);
+        let (mut min, mut max) = match indexed.first() {
+            Some(position) => (project(*position), project(*position)),
+            None => ((0.0, 0.0), (0.0, 0.0)),
+        };
+        for (x, y) in indexed.iter().map(|position| project(*position)) {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        let area = (max.0 - min.0) * (max.1 - min.1);
+        let cell_size = (area / segments.len().max(1) as f64)
+            .sqrt()
+            .max(MIN_CELL_SIZE);
+        let columns = ((max.0 - min.0) / cell_size) as usize + 1;
+        let rows = ((max.1 - min.1) / cell_size) as usize + 1;
+        let points: Vec<(f64, f64)> = (0..graph.node_count() as NodeIndex)
+            .map(|node| {
+                let (x, y) = project(graph.coordinates(node));
+                (x - min.0, y - min.1)
+            })
+            .collect();
+
+        let mut index = SpatialIndex {
+            projection,
+            points,
+            corner: min,
+            cell_size,
+            columns,
+            rows,
+            first_segment: Vec::new(),
+            segments: Vec::new(),
+        };
+        let mut cell_segments: Vec<(usize, (EdgeIndex, NodeIndex, NodeIndex))> = Vec::new();
+        for segment in segments {
+            for cell in index.overlapped_cells(segment.1, segment.2) {
+                cell_segments.push((cell, segment));
+            }
+        }
+        cell_segments.sort_unstable_by_key(|(cell, _)| *cell);
+        let mut first_segment = vec![0u32; columns * rows + 1];
+        for (cell, _) in cell_segments.iter() {
+            first_segment[cell + 1] += 1;
+        }
+        for cell in 0..columns * rows {
+            first_segment[cell + 1] += first_segment[cell];
+        }
+        index.first_segment = first_segment;
+        index.segments = cell_segments
+            .into_iter()
+            .map(|(_, segment)| segment)
+            .collect();
+        index
+    }
+
+    /// Cells overlapped by the bounding box of the segment between `tail` and `head`.
+    fn overlapped_cells(&self, tail: NodeIndex, head: NodeIndex) -> Vec<usize> {
+        let (a, b) = (self.points[tail as usize], self.points[head as usize]);
+        let column = |x: f64| ((x / self.cell_size) as usize).min(self.columns - 1);
+        let row = |y: f64| ((y / self.cell_size) as usize).min(self.rows - 1);
+        let mut cells = Vec::new();
+        for r in row(a.1.min(b.1))..=row(a.1.max(b.1)) {
+            for c in column(a.0.min(b.0))..=column(a.0.max(b.0)) {
+                cells.push(r * self.columns + c);
+            }
+        }
+        cells
+    }
+
+    /// Position of `lat`, `lon` in the coordinates of `points`.
+    fn project(&self, lat: f64, lon: f64) -> (f64, f64) {
+        let (x, y) = self.projection.project(lat, lon);
+        (x - self.corner.0, y - self.corner.1)
+    }
+
+    /// Latitude and longitude of a position in the coordinates of `points`.
+    fn unproject(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        self.projection
+            .unproject(x + self.corner.0, y + self.corner.1)
+    }
+
+    /// Smallest result of `candidate` on the segments around `position`, which returns the
+    /// projected distance of a segment together with the value to return. Cells are
+    /// visited in rings around the cell closest to `position` until no unvisited cell can be
+    /// closer.
+    fn nearest<T>(
+        &self,
+        position: (f64, f64),
+        candidate: impl Fn(&(EdgeIndex, NodeIndex, NodeIndex)) -> (f64, T),
+    ) -> Option<T> {
+        if self.segments.is_empty() {
+            return None;
+        }
+        let (columns, rows) = (self.columns as i64, self.rows as i64);
+        let column = ((position.0 / self.cell_size).floor() as i64).clamp(0, columns - 1);
+        let row = ((position.1 / self.cell_size).floor() as i64).clamp(0, rows - 1);
+        let last_ring = column
+            .max(columns - 1 - column)
+            .max(row)
+            .max(rows - 1 - row);
+        // No cell is closer than the grid itself to positions outside of it.
+        let outside =
+            |value: f64, count: i64| (-value).max(value - count as f64 * self.cell_size).max(0.0);
+        let to_grid = outside(position.0, columns).hypot(outside(position.1, rows));
+
+        let visit = |c: i64, r: i64, best: &mut Option<(f64, T)>| {
+            if c < 0 || c >= columns || r < 0 || r >= rows {
+                return;
+            }
+            let cell = (r * columns + c) as usize;
+            let range = self.first_segment[cell]..self.first_segment[cell + 1];
+            for segment in &self.segments[range.start as usize..range.end as usize] {
+                let (distance, value) = candidate(segment);
+                if best.as_ref().is_none_or(|(d, _)| distance < *d) {
+                    *best = Some((distance, value));
+                }
+            }
+        };
+        let mut best: Option<(f64, T)> = None;
+        for ring in 0..=last_ring {
+            for d in -ring..=ring {
+                visit(column + d, row - ring, &mut best);
+                if ring != 0 {
+                    visit(column + d, row + ring, &mut best);
+                }
+            }
+            for d in 1 - ring..ring {
+                visit(column - ring, row + d, &mut best);
+                visit(column + ring, row + d, &mut best);
+            }
+            if let Some((distance, _)) = best {
+                if distance <= (ring as f64 * self.cell_size).max(to_grid) {
+                    break;
+                }
+            }
+        }
+        best.map(|(_, value)| value)
+    }
+
+    /// Indexed node closest to `lat`, `lon`.
+    pub fn nearest_node(&self, lat: f64, lon: f64) -> Option<NodeIndex> {
+        let (x, y) = self.project(lat, lon);
+        let distance = |node: NodeIndex| {
+            let (px, py) = self.points[node as usize];
+            (px - x).hypot(py - y)
+        };
+        self.nearest((x, y), |(_, tail, head)| {
+            let (tail_distance, head_distance) = (distance(*tail), distance(*head));
+            if tail_distance <= head_distance {
+                (tail_distance, *tail)
+            } else {
+                (head_distance, *head)
+            }
+        })
+    }
+
+    /// Point on an indexed edge closest to `lat`, `lon`. Routes can start and end in the
+    /// middle of the edge, see `Router::route_between`.
+    pub fn nearest_edge(&self, lat: f64, lon: f64) -> Option<Snap> {
+        let (x, y) = self.project(lat, lon);
+        let (edge, fraction, point) = self.nearest((x, y), |(edge, tail, head)| {
+            let (a, b) = (self.points[*tail as usize], self.points[*head as usize]);
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+            let squared_length = dx * dx + dy * dy;
+            let fraction = if squared_length == 0.0 {
+                0.0
+            } else {
+                (((x - a.0) * dx + (y - a.1) * dy) / squared_length).clamp(0.0, 1.0)
+            };
+            let point = (a.0 + fraction * dx, a.1 + fraction * dy);
+            ((point.0 - x).hypot(point.1 - y), (*edge, fraction, point))
+        })?;
+        let (snap_lat, snap_lon) = self.unproject(point);
+        Some(Snap {
+            edge,
+            fraction,
+            lat: snap_lat,
+            lon: snap_lon,
+            distance: coordinate_distance(lat, lon, snap_lat, snap_lon),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpatialIndex;
+    use crate::graph::Graph;
+    use crate::graph::NodeIndex;
+    use crate::graph::Weighting;
+    use crate::map::fixture;
+
+    fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+        (a.0 - b.0).hypot(a.1 - b.1)
+    }
+
+    /// Query points on a lattice reaching beyond the grid on all sides, and some far away.
+    fn queries() -> Vec<(f64, f64)> {
+        let mut queries = Vec::new();
+        for i in -6..18 {
+            for j in -6..18 {
+                queries.push((i as f64 * 0.00071, j as f64 * 0.00067));
+            }
+        }
+        queries.extend([(0.5, 0.003), (-0.4, -0.7), (0.004, 1.2), (-0.02, 0.004)]);
+        queries
+    }
+
+    fn index() -> (Graph, SpatialIndex) {
+        let graph = fixture::grid_graph(10, Weighting::Distance);
+        let index = SpatialIndex::new(&graph);
+        // Queries have to cross cells for the ring search to matter.
+        assert!(index.columns > 3 && index.rows > 3);
+        (graph, index)
+    }
+
+    #[test]
+    fn nearest_node_matches_brute_force() {
+        let (graph, index) = index();
+        let isolated = graph.node_count() as NodeIndex - 1;
+        for (lat, lon) in queries() {
+            let query = index.project(lat, lon);
+            let expected = (0..isolated)
+                .map(|node| distance(index.points[node as usize], query))
+                .fold(f64::INFINITY, f64::min);
+            let node = index.nearest_node(lat, lon).unwrap();
+            assert_ne!(node, isolated);
+            let found = distance(index.points[node as usize], query);
+            assert!((found - expected).abs() < 1e-6, "{},{}", lat, lon);
+        }
+    }
+
+    #[test]
+    fn nearest_edge_matches_brute_force() {
+        let (_, index) = index();
+        for (lat, lon) in queries() {
+            let query = index.project(lat, lon);
+            let expected = index
+                .segments
+                .iter()
+                .map(|(_, tail, head)| {
+                    let (a, b) = (index.points[*tail as usize], index.points[*head as usize]);
+                    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+                    let fraction = (((query.0 - a.0) * dx + (query.1 - a.1) * dy)
+                        / (dx * dx + dy * dy))
+                        .clamp(0.0, 1.0);
+                    distance((a.0 + fraction * dx, a.1 + fraction * dy), query)
+                })
+                .fold(f64::INFINITY, f64::min);
+            let snap = index.nearest_edge(lat, lon).unwrap();
+            let found = distance(index.project(snap.lat, snap.lon), query);
+            assert!((found - expected).abs() < 1e-6, "{},{}", lat, lon);
+        }
+    }
+}
@@ -0,0 +1,774 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use osmpbfreader::NodeId;
+use osmpbfreader::Tags;
+use osmpbfreader::WayId;
+
+use crate::alt::Landmarks;
+use crate::ch::ContractionHierarchy;
+use crate::graph::Weighting;
+use crate::loader::DanglingPolicy;
+use crate::profile::Profile;
+use crate::Map;
+use crate::Prune;
+
+const MAGIC: [u8; 8] = *b"SPGRAPH\0";
+
+/// Version of the file format, increased whenever the layout of any stored type changes.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Error while reading a graph file.
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    /// The file is not a graph file.
+    BadMagic,
+    /// The file was written by a different version of the format.
+    Version {
+        found: u32,
+        expected: u32,
+    },
+    /// The file was built from a different source file or with different options.
+    Stale(String),
+    /// The file ends early or contains invalid values.
+    Corrupt(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::Io(err) => write!(f, "I/O error: {}", err),
+            StorageError::BadMagic => write!(f, "not a graph file"),
+            StorageError::Version { found, expected } => write!(
+                f,
+                "format version {} is not the supported version {}",
+                found, expected
+            ),
+            StorageError::Stale(reason) => write!(f, "stale graph file: {}", reason),
+            StorageError::Corrupt(reason) => write!(f, "corrupt graph file: {}", reason),
+        }
+    }
+}
+
+impl Error for StorageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StorageError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+/// Maximum size of a PBF blob header, as given by the format specification.
+const MAX_BLOB_HEADER_SIZE: u64 = 64 * 1024;
+/// Maximum size of a PBF blob, as given by the format specification.
+const MAX_BLOB_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Hash identifying the source of a graph file from the size, modification time and header
+/// block of the PBF file at `path`, so checking a graph file does not read the whole source.
+pub fn source_hash(path: &Path) -> io::Result<u64> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64);
+    let mut hash = fnv1a(FNV_OFFSET_BASIS, &metadata.len().to_le_bytes());
+    hash = fnv1a(hash, &modified.to_le_bytes());
+    Ok(fnv1a(hash, &header_block(file)?))
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Continues the FNV-1a `hash` over `bytes`.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Bytes of the first block of a PBF file, its length, blob header and blob, which hold the
+/// `OSMHeader` with the replication timestamp. As much of it as there is in shorter or
+/// malformed files.
+fn header_block(file: File) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut reader = file.take(4);
+    reader.read_to_end(&mut bytes)?;
+    let Ok(length) = <[u8; 4]>::try_from(&bytes[..]) else {
+        return Ok(bytes);
+    };
+    reader.set_limit(u32::from_be_bytes(length).min(MAX_BLOB_HEADER_SIZE as u32) as u64);
+    reader.read_to_end(&mut bytes)?;
+    let data_size = blob_data_size(&bytes[4..]).unwrap_or(0);
+    reader.set_limit(data_size.min(MAX_BLOB_SIZE));
+    reader.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// `datasize` field of an encoded PBF `BlobHeader`, `None` if it is missing or malformed.
+fn blob_data_size(mut bytes: &[u8]) -> Option<u64> {
+    fn varint(bytes: &mut &[u8]) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = bytes.split_first()?;
+            *bytes = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    while !bytes.is_empty() {
+        let key = varint(&mut bytes)?;
+        match key & 7 {
+            0 => {
+                let value = varint(&mut bytes)?;
+                if key >> 3 == 3 {
+                    return Some(value);
+                }
+            }
+            2 => {
+                let len = usize::try_from(varint(&mut bytes)?).ok()?;
+                bytes = bytes.get(len..)?;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Writes values in little endian to the underlying writer.
+pub(crate) struct Encoder<W: Write> {
+    writer: W,
+}
+
+/// Reads values written by `Encoder` from a buffer.
+pub(crate) struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StorageError> {
+        if self.bytes.len() < len {
+            return Err(corrupt("unexpected end of file"));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], StorageError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+pub(crate) fn corrupt(reason: &str) -> StorageError {
+    StorageError::Corrupt(String::from(reason))
+}
+
+/// Checks that `first` are valid offsets of `count` groups into an array of `len` items.
+pub(crate) fn check_offsets(first: &[u32], count: usize, len: usize) -> Result<(), StorageError> {
+    if first.len() != count + 1
+        || first[0] != 0
+        || first[count] as usize != len
+        || first.windows(2).any(|pair| pair[0] > pair[1])
+    {
+        return Err(corrupt("invalid offsets"));
+    }
+    Ok(())
+}
+
+/// Checks that all `indices` are smaller than `bound`.
+pub(crate) fn check_indices(
+    indices: impl IntoIterator<Item = u32>,
+    bound: usize,
+) -> Result<(), StorageError> {
+    if indices.into_iter().any(|index| index as usize >= bound) {
+        return Err(corrupt("index out of range"));
+    }
+    Ok(())
+}
+
+/// Value that can be stored in a graph file.
+pub(crate) trait Encode: Sized {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()>;
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError>;
+}
+
+macro_rules! encode_number {
+    ($($type:ty),*) => {
+        $(
+            impl Encode for $type {
+                fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+                    encoder.writer.write_all(&self.to_le_bytes())
+                }
+
+                fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+                    Ok(<$type>::from_le_bytes(decoder.take_array()?))
+                }
+            }
+        )*
+    };
+}
+
+encode_number!(u8, u32, i32, u64, i64, f64);
+
+impl Encode for usize {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        (*self as u64).encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        usize::try_from(u64::decode(decoder)?).map_err(|_| corrupt("length out of range"))
+    }
+}
+
+impl<W: Write> Encoder<W> {
+    fn write_str(&mut self, value: &str) -> io::Result<()> {
+        value.len().encode(self)?;
+        self.writer.write_all(value.as_bytes())
+    }
+}
+
+impl Encode for String {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        encoder.write_str(self)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        let len = usize::decode(decoder)?;
+        String::from_utf8(decoder.take(len)?.to_vec()).map_err(|_| corrupt("invalid string"))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.len().encode(encoder)?;
+        self.iter().try_for_each(|value| value.encode(encoder))
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        let len = usize::decode(decoder)?;
+        // Every value takes at least a byte, which bounds the allocation for corrupt lengths.
+        let mut values = Vec::with_capacity(len.min(decoder.bytes.len()));
+        for _ in 0..len {
+            values.push(T::decode(decoder)?);
+        }
+        Ok(values)
+    }
+}
+
+impl<W: Write> Encoder<W> {
+    /// Writes a borrowed optional value the way `Option<T>` is encoded.
+    fn write_option<T: Encode>(&mut self, value: Option<&T>) -> io::Result<()> {
+        match value {
+            Some(value) => {
+                1u8.encode(self)?;
+                value.encode(self)
+            }
+            None => 0u8.encode(self),
+        }
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        encoder.write_option(self.as_ref())
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        match u8::decode(decoder)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(decoder)?)),
+            _ => Err(corrupt("invalid option")),
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.0.encode(encoder)?;
+        self.1.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        Ok((A::decode(decoder)?, B::decode(decoder)?))
+    }
+}
+
+impl Encode for NodeId {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.0.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        Ok(NodeId(i64::decode(decoder)?))
+    }
+}
+
+impl Encode for WayId {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.0.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        Ok(WayId(i64::decode(decoder)?))
+    }
+}
+
+impl Encode for Tags {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.len().encode(encoder)?;
+        for (key, value) in self.iter() {
+            encoder.write_str(key)?;
+            encoder.write_str(value)?;
+        }
+        Ok(())
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        let mut tags = Tags::new();
+        for _ in 0..usize::decode(decoder)? {
+            let key = String::decode(decoder)?;
+            tags.insert(key.into(), String::decode(decoder)?.into());
+        }
+        Ok(tags)
+    }
+}
+
+impl Encode for Profile {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.to_string().encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        String::decode(decoder)?
+            .parse()
+            .map_err(StorageError::Corrupt)
+    }
+}
+
+impl Encode for Weighting {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.to_string().encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        String::decode(decoder)?
+            .parse()
+            .map_err(StorageError::Corrupt)
+    }
+}
+
+impl Encode for Prune {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.to_string().encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        String::decode(decoder)?
+            .parse()
+            .map_err(StorageError::Corrupt)
+    }
+}
+
+impl Encode for DanglingPolicy {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> io::Result<()> {
+        self.to_string().encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, StorageError> {
+        String::decode(decoder)?
+            .parse()
+            .map_err(StorageError::Corrupt)
+    }
+}
+
+/// Describes what a graph file was built from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub version: u32,
+    /// `source_hash` of the PBF file the graph was built from.
+    pub source_hash: u64,
+    pub profile: Profile,
+    pub weighting: Weighting,
+    /// How ways with nodes missing from the source were handled.
+    pub dangling: DanglingPolicy,
+    /// Components the map was pruned to.
+    pub prune: Prune,
+}
+
+impl Header {
+    /// Header of a graph file in the current format built from the source with
+    /// `source_hash` using `profile`, `weighting` and the `dangling` policy, pruned to the
+    /// components selected by `prune`.
+    pub fn new(
+        source_hash: u64,
+        profile: Profile,
+        weighting: Weighting,
+        dangling: DanglingPolicy,
+        prune: Prune,
+    ) -> Self {
+        Header {
+            version: FORMAT_VERSION,
+            source_hash,
+            profile,
+            weighting,
+            dangling,
+            prune,
+        }
+    }
+
+    /// Rejects graph files built from something else than what `expected` describes.
+    fn check(&self, expected: &Header) -> Result<(), StorageError> {
+        if self.source_hash != expected.source_hash {
+            return Err(StorageError::Stale(String::from(
+                "the source file has changed",
+            )));
+        }
+        if self.profile != expected.profile {
+            return Err(StorageError::Stale(format!(
+                "built for the {} profile",
+                self.profile
+            )));
+        }
+        if self.weighting != expected.weighting {
+            return Err(StorageError::Stale(format!(
+                "built for the {} weighting",
+                self.weighting
+            )));
+        }
+        if self.dangling != expected.dangling {
+            return Err(StorageError::Stale(format!(
+                "built with the {} dangling reference policy",
+                self.dangling
+            )));
+        }
+        if self.prune != expected.prune {
+            return Err(StorageError::Stale(format!(
+                "built with pruning {}",
+                self.prune
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Contents of a graph file.
+#[derive(Debug, Clone)]
+pub struct GraphFile {
+    pub header: Header,
+    pub map: Map,
+    pub hierarchy: Option<ContractionHierarchy>,
+    pub landmarks: Option<Landmarks>,
+}
+
+/// Writes `map` with its optional preprocessing to a graph file at `path`.
+pub fn save(
+    path: &Path,
+    header: &Header,
+    map: &Map,
+    hierarchy: Option<&ContractionHierarchy>,
+    landmarks: Option<&Landmarks>,
+) -> io::Result<()> {
+    let mut encoder = Encoder {
+        writer: BufWriter::new(File::create(path)?),
+    };
+    encoder.writer.write_all(&MAGIC)?;
+    header.version.encode(&mut encoder)?;
+    header.source_hash.encode(&mut encoder)?;
+    header.profile.encode(&mut encoder)?;
+    header.weighting.encode(&mut encoder)?;
+    header.dangling.encode(&mut encoder)?;
+    header.prune.encode(&mut encoder)?;
+    map.encode(&mut encoder)?;
+    encoder.write_option(hierarchy)?;
+    encoder.write_option(landmarks)?;
+    encoder.writer.flush()
+}
+
+/// Reads the graph file at `path`, rejecting it unless it was written in the current format
+/// from the source, profile, weighting, dangling reference policy and pruning given by
+/// `expected`.
+pub fn load(path: &Path, expected: &Header) -> Result<GraphFile, StorageError> {
+    let bytes = std::fs::read(path)?;
+    let mut decoder = Decoder { bytes: &bytes };
+    if decoder.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(StorageError::BadMagic);
+    }
+    let version = u32::decode(&mut decoder)?;
+    if version != FORMAT_VERSION {
+        return Err(StorageError::Version {
+            found: version,
+            expected: FORMAT_VERSION,
+        });
+    }
+    let header = Header {
+        version,
+        source_hash: u64::decode(&mut decoder)?,
+        profile: Profile::decode(&mut decoder)?,
+        weighting: Weighting::decode(&mut decoder)?,
+        dangling: DanglingPolicy::decode(&mut decoder)?,
+        prune: Prune::decode(&mut decoder)?,
+    };
+    header.check(expected)?;
+
+    let map = Map::decode(&mut decoder)?;
+    let hierarchy = Option::<ContractionHierarchy>::decode(&mut decoder)?;
+    let landmarks = Option::<Landmarks>::decode(&mut decoder)?;
+    // Routing indexes these by the node indices of the graph without further checks.
+    if hierarchy.as_ref().is_some_and(|h| !h.matches(map.graph())) {
+        return Err(corrupt("hierarchy does not match the graph"));
+    }
+    if landmarks.as_ref().is_some_and(|l| !l.matches(map.graph())) {
+        return Err(corrupt("landmark tables do not match the graph"));
+    }
+    if !decoder.bytes.is_empty() {
+        return Err(corrupt("trailing data after the graph"));
+    }
+    Ok(GraphFile {
+        header,
+        map,
+        hierarchy,
+        landmarks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use osmpbfreader::NodeId;
+    use osmpbfreader::WayId;
+
+    use super::load;
+    use super::save;
+    use super::Header;
+    use super::StorageError;
+    use crate::alt::LandmarkSelection;
+    use crate::alt::Landmarks;
+    use crate::ch::ContractionHierarchy;
+    use crate::graph::Weighting;
+    use crate::map::fixture;
+    use crate::profile::Profile;
+    use crate::restriction::RestrictionKind;
+    use crate::restriction::TurnRestriction;
+    use crate::restriction::Via;
+    use crate::router::Algorithm;
+    use crate::router::Preprocessing;
+    use crate::router::Router;
+    use crate::DanglingPolicy;
+    use crate::Map;
+    use crate::Prune;
+
+    /// Path in the temporary directory that no other test or test run uses.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "shortest_path-{}-{}.graph",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn header() -> Header {
+        Header::new(
+            42,
+            Profile::Car,
+            Weighting::Time,
+            DanglingPolicy::Truncate,
+            Prune::None,
+        )
+    }
+
+    /// Square of ways 10 to 13 between nodes 1 to 4 with a diagonal way 14 from 1 to 3 and no
+    /// left turn from 10 to 14.
+    fn map() -> Map {
+        let nodes = fixture::nodes(&[(0.0, 0.0), (0.0, 0.001), (0.001, 0.001), (0.001, 0.0)]);
+        let ways = fixture::ways(&[
+            (10, &[4, 1]),
+            (11, &[1, 2]),
+            (12, &[2, 3]),
+            (13, &[3, 4]),
+            (14, &[1, 3]),
+        ]);
+        let restriction = TurnRestriction {
+            kind: RestrictionKind::No,
+            from: WayId(10),
+            via: Via::Node(NodeId(1)),
+            to: WayId(14),
+        };
+        Map::new(nodes, ways, &[restriction], Profile::Car, Weighting::Time)
+    }
+
+    /// Bytes of the graph file of `map` with its preprocessing.
+    fn saved(map: &Map, name: &str) -> Vec<u8> {
+        let path = temp_path(name);
+        let hierarchy = ContractionHierarchy::new(map.graph());
+        let landmarks = Landmarks::new(map.graph(), 2, LandmarkSelection::Farthest, 1);
+        save(&path, &header(), map, Some(&hierarchy), Some(&landmarks)).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    /// Result of loading a graph file with `bytes` expecting `expected`.
+    fn load_bytes(bytes: &[u8], expected: &Header, name: &str) -> Result<Map, StorageError> {
+        let path = temp_path(name);
+        std::fs::write(&path, bytes).unwrap();
+        let loaded = load(&path, expected);
+        std::fs::remove_file(&path).unwrap();
+        loaded.map(|file| file.map)
+    }
+
+    #[test]
+    fn round_trip() {
+        let map = map();
+        let path = temp_path("round_trip");
+        let hierarchy = ContractionHierarchy::new(map.graph());
+        let landmarks = Landmarks::new(map.graph(), 2, LandmarkSelection::Farthest, 1);
+        save(&path, &header(), &map, Some(&hierarchy), Some(&landmarks)).unwrap();
+        let file = load(&path, &header()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(file.header, header());
+        assert_eq!(file.map.ways(), map.ways());
+        assert_eq!(file.map.restriction_count(), map.restriction_count());
+        assert_eq!(file.map.graph().edge_count(), map.graph().edge_count());
+        assert_eq!(
+            file.landmarks.as_ref().unwrap().landmarks(),
+            landmarks.landmarks()
+        );
+        let preprocessing = Preprocessing {
+            hierarchy: file.hierarchy,
+            landmarks: file.landmarks,
+        };
+        let router = Router::new(
+            &file.map,
+            preprocessing,
+            &Algorithm::ALL,
+            2,
+            LandmarkSelection::Farthest,
+        );
+        for from in 1..=4 {
+            for to in 1..=4 {
+                let (from, to) = (NodeId(from), NodeId(to));
+                let expected = map.shortest_path(from, to).unwrap();
+                for algorithm in Algorithm::ALL {
+                    let path = router.route(algorithm, from, to).unwrap();
+                    assert_eq!(path.nodes, expected.nodes, "{}", algorithm);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn without_preprocessing() {
+        let map = map();
+        let path = temp_path("without_preprocessing");
+        save(&path, &header(), &map, None, None).unwrap();
+        let file = load(&path, &header()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(file.hierarchy.is_none() && file.landmarks.is_none());
+        assert_eq!(file.map.ways(), map.ways());
+    }
+
+    #[test]
+    fn stale_headers() {
+        let bytes = saved(&map(), "stale_headers");
+        let expected = [
+            Header {
+                source_hash: 43,
+                ..header()
+            },
+            Header {
+                profile: Profile::Bicycle,
+                ..header()
+            },
+            Header {
+                weighting: Weighting::Distance,
+                ..header()
+            },
+            Header {
+                dangling: DanglingPolicy::Skip,
+                ..header()
+            },
+            Header {
+                prune: Prune::Strong,
+                ..header()
+            },
+        ];
+        for (i, expected) in expected.iter().enumerate() {
+            let loaded = load_bytes(&bytes, expected, "stale_headers");
+            assert!(matches!(loaded, Err(StorageError::Stale(_))), "field {}", i);
+        }
+    }
+
+    #[test]
+    fn other_files_and_versions() {
+        let mut bytes = saved(&map(), "other_versions");
+        bytes[0] = b'X';
+        let loaded = load_bytes(&bytes, &header(), "other_versions");
+        assert!(matches!(loaded, Err(StorageError::BadMagic)));
+        bytes[0] = b'S';
+        // The version follows the magic bytes.
+        bytes[8] ^= 0x80;
+        let loaded = load_bytes(&bytes, &header(), "other_versions");
+        assert!(matches!(loaded, Err(StorageError::Version { .. })));
+    }
+
+    #[test]
+    fn truncated_files() {
+        let bytes = saved(&map(), "truncated");
+        for len in 0..bytes.len() {
+            let loaded = load_bytes(&bytes[..len], &header(), "truncated");
+            assert!(
+                matches!(
+                    loaded,
+                    Err(StorageError::Corrupt(_)) | Err(StorageError::BadMagic)
+                ),
+                "{} bytes",
+                len
+            );
+        }
+        let mut longer = bytes.clone();
+        longer.push(0);
+        let loaded = load_bytes(&longer, &header(), "truncated");
+        assert!(matches!(loaded, Err(StorageError::Corrupt(_))));
+    }
+
+    #[test]
+    fn flipped_bits() {
+        let bytes = saved(&map(), "flipped");
+        for i in 0..bytes.len() {
+            let mut flipped = bytes.clone();
+            flipped[i] ^= 1 << (i % 8);
+            // Flips in values without invariants, like coordinates, go unnoticed.
+            match load_bytes(&flipped, &header(), "flipped") {
+                Ok(_)
+                | Err(StorageError::Corrupt(_))
+                | Err(StorageError::BadMagic)
+                | Err(StorageError::Version { .. })
+                | Err(StorageError::Stale(_)) => {}
+                Err(StorageError::Io(err)) => panic!("byte {}: {}", i, err),
+            }
+        }
+    }
+}